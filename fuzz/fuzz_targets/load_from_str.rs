@@ -0,0 +1,15 @@
+#![no_main]
+
+//! Any input must produce either a `Map` or an `Error`, never a panic.
+//!
+//! Run with `cargo +nightly fuzz run load_from_str`. The maps in
+//! `tests/data` make a good starting corpus.
+
+use libfuzzer_sys::fuzz_target;
+use tiled_json_rs::Map;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(s) = std::str::from_utf8(data) {
+        let _ = Map::load_from_str(s);
+    }
+});
@@ -0,0 +1,23 @@
+#![no_main]
+
+//! Any TMX map or TSX tileset must produce either a value or an `Error`,
+//! never a panic.
+//!
+//! Run with `cargo +nightly fuzz run load_xml`. The `.tmx` and `.tsx`
+//! files in `tests/data` make a good starting corpus.
+
+use libfuzzer_sys::fuzz_target;
+use std::path::{Path, PathBuf};
+use tiled_json_rs::{External, InMemory, Map};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(s) = std::str::from_utf8(data) {
+        let _ = Map::load_from_str(s);
+    }
+    let tile_set = External {
+        first_gid: 1,
+        source: PathBuf::from("tileset.tsx"),
+    };
+    let files = InMemory::new().with("tileset.tsx", data);
+    let _ = tile_set.load_with(Path::new(""), &files);
+});
@@ -0,0 +1,480 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Converting between tile coordinates and pixels for each orientation,
+//! following the same layout Tiled draws maps in.
+//!
+//! Pixel positions are relative to the top-left of the map. Tile
+//! coordinates are signed as the chunks of an infinite map can be at
+//! negative positions.
+
+use crate::{
+    Gid, Layer, LayerInfo, LayerType, Map, Orientation, Rect, StaggerAxis,
+    StaggerIndex, Vec2,
+};
+
+impl Map {
+    /// Returns the top-left of the tile's bounding box in pixels. For an
+    /// isometric map the bounding box is that of the diamond, for a
+    /// hexagonal one that of the hexagon.
+    pub fn tile_to_pixel(&self, tile: Vec2<i32>) -> Vec2<f32> {
+        let grid = Grid::new(self);
+        let (x, y) = (tile.x as f32, tile.y as f32);
+        match self.orientation {
+            Orientation::Orthogonal => Vec2 {
+                x: x * grid.tile_width,
+                y: y * grid.tile_height,
+            },
+            Orientation::Isometric => Vec2 {
+                x: (x - y) * grid.tile_width / 2.0 + grid.origin_x
+                    - grid.tile_width / 2.0,
+                y: (x + y) * grid.tile_height / 2.0,
+            },
+            Orientation::Staggered | Orientation::Hexagonal => {
+                if grid.stagger_x {
+                    let mut pixel_y = y * (grid.tile_height + grid.side_y);
+                    if grid.is_staggered(tile.x) {
+                        pixel_y += grid.row_height;
+                    }
+                    Vec2 {
+                        x: x * grid.column_width,
+                        y: pixel_y,
+                    }
+                } else {
+                    let mut pixel_x = x * (grid.tile_width + grid.side_x);
+                    if grid.is_staggered(tile.y) {
+                        pixel_x += grid.column_width;
+                    }
+                    Vec2 {
+                        x: pixel_x,
+                        y: y * grid.row_height,
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the tile whose shape contains the pixel. Any pixel gives a
+    /// tile, which may be outside of the map.
+    pub fn pixel_to_tile(&self, pixel: Vec2<f32>) -> Vec2<i32> {
+        let grid = Grid::new(self);
+        match self.orientation {
+            Orientation::Orthogonal => Vec2 {
+                x: (pixel.x / grid.tile_width).floor() as i32,
+                y: (pixel.y / grid.tile_height).floor() as i32,
+            },
+            Orientation::Isometric => {
+                let x = (pixel.x - grid.origin_x) / grid.tile_width;
+                let y = pixel.y / grid.tile_height;
+                Vec2 {
+                    x: (y + x).floor() as i32,
+                    y: (y - x).floor() as i32,
+                }
+            }
+            Orientation::Staggered => grid.staggered_to_tile(pixel),
+            Orientation::Hexagonal => grid.hexagonal_to_tile(pixel),
+        }
+    }
+
+    /// Returns the tiles sharing an edge with the tile: 4 for orthogonal,
+    /// isometric and staggered maps, and 6 for hexagonal maps. They are in
+    /// clockwise order. Those outside a finite map are left out.
+    pub fn neighbours(&self, tile: Vec2<i32>) -> Vec<Vec2<i32>> {
+        let grid = Grid::new(self);
+        let Vec2 { x, y } = tile;
+        let neighbours = match self.orientation {
+            Orientation::Orthogonal | Orientation::Isometric => {
+                vec![(x, y - 1), (x + 1, y), (x, y + 1), (x - 1, y)]
+            }
+            Orientation::Staggered => vec![
+                grid.top_left(x, y),
+                grid.top_right(x, y),
+                grid.bottom_right(x, y),
+                grid.bottom_left(x, y),
+            ],
+            Orientation::Hexagonal if grid.stagger_x => vec![
+                (x, y - 1),
+                grid.top_right(x, y),
+                grid.bottom_right(x, y),
+                (x, y + 1),
+                grid.bottom_left(x, y),
+                grid.top_left(x, y),
+            ],
+            Orientation::Hexagonal => vec![
+                grid.top_left(x, y),
+                grid.top_right(x, y),
+                (x + 1, y),
+                grid.bottom_right(x, y),
+                grid.bottom_left(x, y),
+                (x - 1, y),
+            ],
+        };
+        neighbours
+            .into_iter()
+            .map(|(x, y)| Vec2 { x, y })
+            .filter(|tile| self.infinite || self.contains(tile))
+            .collect()
+    }
+
+    /// Returns the cell of a tile layer under the pixel, and the GID there,
+    /// which is empty if there's no tile. `None` if the pixel is outside of
+    /// the layer, or the layer isn't a tile layer of this map.
+    ///
+    /// The offsets of the layer and the groups it's in are taken in to
+    /// account. Parallax is not, as if the view were centred on the map's
+    /// parallax origin, where no layer has moved; see
+    /// `tile_at_pixel_in_view`.
+    pub fn tile_at_pixel(
+        &self,
+        pixel: Vec2<f32>,
+        layer: &Layer,
+    ) -> Option<(Vec2<i32>, Gid)> {
+        let origin = Vec2 {
+            x: self.parallax_origin_x,
+            y: self.parallax_origin_y,
+        };
+        self.tile_at_pixel_in_view(pixel, layer, origin)
+    }
+
+    /// As `tile_at_pixel`, for a view centred on `view_centre` in map
+    /// pixels. A layer with a parallax factor other than 1 has moved
+    /// relative to the map by `(1 - factor) * (view_centre - origin)`,
+    /// where the origin is the map's `parallax_origin_x` and `_y`, which is
+    /// undone before finding the tile.
+    pub fn tile_at_pixel_in_view(
+        &self,
+        pixel: Vec2<f32>,
+        layer: &Layer,
+        view_centre: Vec2<f32>,
+    ) -> Option<(Vec2<i32>, Gid)> {
+        let tiles = match &layer.layer_type {
+            LayerType::TileLayer(tiles) => tiles,
+            _ => return None,
+        };
+        let position = self.layer_position(layer, view_centre)?;
+        let tile = self.pixel_to_tile(Vec2 {
+            x: pixel.x - position.x,
+            y: pixel.y - position.y,
+        });
+        let gid = tiles.get(tile.x, tile.y)?;
+        Some((tile, gid))
+    }
+
+    /// Where to draw the image of an image layer for the `viewport`, in
+    /// map pixels. This is once, or for a repeating image as many times as
+    /// it takes to cover the viewport, at the layer's offsets and
+    /// parallax, and those of the groups it's in. Only the rectangles
+    /// which overlap the viewport are returned.
+    ///
+    /// Empty if the layer isn't an image layer of this map, or the size of
+    /// its image isn't known. Maps saved by older versions of Tiled don't
+    /// give it, so set `image_width` and `image_height` from the loaded
+    /// image first.
+    pub fn image_layer_rects(
+        &self,
+        layer: &Layer,
+        viewport: Rect,
+    ) -> Vec<Rect> {
+        let image = match &layer.layer_type {
+            LayerType::ImageLayer(image) => image,
+            _ => return Vec::new(),
+        };
+        if image.image_width == 0 || image.image_height == 0 {
+            return Vec::new();
+        }
+        let view_centre = Vec2 {
+            x: viewport.x + viewport.width / 2.0,
+            y: viewport.y + viewport.height / 2.0,
+        };
+        let position = match self.layer_position(layer, view_centre) {
+            Some(position) => position,
+            None => return Vec::new(),
+        };
+
+        let (width, height) =
+            (image.image_width as f32, image.image_height as f32);
+        let columns = repeats(
+            position.x,
+            width,
+            image.repeat_x,
+            viewport.x,
+            viewport.width,
+        );
+        let rows = repeats(
+            position.y,
+            height,
+            image.repeat_y,
+            viewport.y,
+            viewport.height,
+        );
+        rows.iter()
+            .flat_map(|&y| {
+                columns.iter().map(move |&x| Rect {
+                    x,
+                    y,
+                    width,
+                    height,
+                })
+            })
+            .collect()
+    }
+
+    /// The position of a layer of this map, for a view centred on
+    /// `view_centre`
+    fn layer_position(
+        &self,
+        layer: &Layer,
+        view_centre: Vec2<f32>,
+    ) -> Option<Vec2<f32>> {
+        // The same layer, rather than an equal one
+        let LayerInfo {
+            offset, parallax, ..
+        } = self
+            .iter_layers()
+            .find(|info| std::ptr::eq(info.layer, layer))?;
+        Some(Vec2 {
+            x: offset.x
+                + (1.0 - parallax.x) * (view_centre.x - self.parallax_origin_x),
+            y: offset.y
+                + (1.0 - parallax.y) * (view_centre.y - self.parallax_origin_y),
+        })
+    }
+
+    /// True if the tile is within the map's `width` and `height`
+    pub fn contains(&self, tile: &Vec2<i32>) -> bool {
+        tile.x >= 0
+            && tile.y >= 0
+            && (tile.x as u32) < self.width
+            && (tile.y as u32) < self.height
+    }
+}
+
+/// Where along one axis an image of `size` at `start` is drawn to cover
+/// the part of the view from `view` of `length`, once if it isn't repeated
+fn repeats(
+    start: f32,
+    size: f32,
+    repeat: bool,
+    view: f32,
+    length: f32,
+) -> Vec<f32> {
+    let first = if repeat {
+        start + ((view - start) / size).floor() * size
+    } else {
+        start
+    };
+    let mut positions = Vec::new();
+    let mut position = first;
+    while position < view + length && position + size > view {
+        positions.push(position);
+        if !repeat {
+            break;
+        }
+        position += size;
+    }
+    positions
+}
+
+/// The measurements of a map's grid, as Tiled's renderers use them. A
+/// staggered map is a hexagonal one with sides of no length.
+struct Grid {
+    tile_width: f32,
+    tile_height: f32,
+    /// Isometric maps are shifted right so the left corner of the map is
+    /// at 0
+    origin_x: f32,
+    stagger_x: bool,
+    stagger_even: bool,
+    side_x: f32,
+    side_y: f32,
+    side_offset_x: f32,
+    side_offset_y: f32,
+    column_width: f32,
+    row_height: f32,
+}
+
+impl Grid {
+    fn new(map: &Map) -> Self {
+        let tile_width = map.tile_width as f32;
+        let tile_height = map.tile_height as f32;
+        // Tiled's defaults when a map doesn't say
+        let stagger_x = map.stagger_axis == Some(StaggerAxis::X);
+        let stagger_even = map.stagger_index == Some(StaggerIndex::Even);
+        let side = match map.orientation {
+            Orientation::Hexagonal => map.hex_side_length.unwrap_or(0) as f32,
+            _ => 0.0,
+        };
+        let (side_x, side_y) =
+            if stagger_x { (side, 0.0) } else { (0.0, side) };
+        let side_offset_x = (tile_width - side_x) / 2.0;
+        let side_offset_y = (tile_height - side_y) / 2.0;
+        Grid {
+            tile_width,
+            tile_height,
+            origin_x: map.height as f32 * tile_width / 2.0,
+            stagger_x,
+            stagger_even,
+            side_x,
+            side_y,
+            side_offset_x,
+            side_offset_y,
+            column_width: side_offset_x + side_x,
+            row_height: side_offset_y + side_y,
+        }
+    }
+
+    /// True if the column, or row, is the one shifted by half a tile
+    fn is_staggered(&self, index: i32) -> bool {
+        (index & 1 == 1) != self.stagger_even
+    }
+
+    /// True if the tile is in a shifted column, or row
+    fn is_shifted(&self, x: i32, y: i32) -> bool {
+        self.is_staggered(if self.stagger_x { x } else { y })
+    }
+
+    fn top_left(&self, x: i32, y: i32) -> (i32, i32) {
+        match (self.stagger_x, self.is_shifted(x, y)) {
+            (false, true) => (x, y - 1),
+            (false, false) => (x - 1, y - 1),
+            (true, true) => (x - 1, y),
+            (true, false) => (x - 1, y - 1),
+        }
+    }
+
+    fn top_right(&self, x: i32, y: i32) -> (i32, i32) {
+        match (self.stagger_x, self.is_shifted(x, y)) {
+            (false, true) => (x + 1, y - 1),
+            (false, false) => (x, y - 1),
+            (true, true) => (x + 1, y),
+            (true, false) => (x + 1, y - 1),
+        }
+    }
+
+    fn bottom_left(&self, x: i32, y: i32) -> (i32, i32) {
+        match (self.stagger_x, self.is_shifted(x, y)) {
+            (false, true) => (x, y + 1),
+            (false, false) => (x - 1, y + 1),
+            (true, true) => (x - 1, y + 1),
+            (true, false) => (x - 1, y),
+        }
+    }
+
+    fn bottom_right(&self, x: i32, y: i32) -> (i32, i32) {
+        match (self.stagger_x, self.is_shifted(x, y)) {
+            (false, true) => (x + 1, y + 1),
+            (false, false) => (x, y + 1),
+            (true, true) => (x + 1, y + 1),
+            (true, false) => (x + 1, y),
+        }
+    }
+
+    /// Finds the rectangle of two diamonds the pixel is in, then which of
+    /// the diamonds around it the pixel is in the corner of
+    fn staggered_to_tile(&self, pixel: Vec2<f32>) -> Vec2<i32> {
+        let Vec2 { mut x, mut y } = pixel;
+        if self.stagger_x {
+            if self.stagger_even {
+                x -= self.side_offset_x;
+            }
+        } else if self.stagger_even {
+            y -= self.side_offset_y;
+        }
+
+        let mut tile_x = (x / self.tile_width).floor() as i32;
+        let mut tile_y = (y / self.tile_height).floor() as i32;
+        let rel_x = x - tile_x as f32 * self.tile_width;
+        let rel_y = y - tile_y as f32 * self.tile_height;
+        if self.stagger_x {
+            tile_x = tile_x * 2 + self.stagger_even as i32;
+        } else {
+            tile_y = tile_y * 2 + self.stagger_even as i32;
+        }
+
+        let y_pos = rel_x * (self.tile_height / self.tile_width);
+        let (x, y) = if self.side_offset_y - y_pos > rel_y {
+            self.top_left(tile_x, tile_y)
+        } else if -self.side_offset_y + y_pos > rel_y {
+            self.top_right(tile_x, tile_y)
+        } else if self.side_offset_y + y_pos < rel_y {
+            self.bottom_left(tile_x, tile_y)
+        } else if self.side_offset_y * 3.0 - y_pos < rel_y {
+            self.bottom_right(tile_x, tile_y)
+        } else {
+            (tile_x, tile_y)
+        };
+        Vec2 { x, y }
+    }
+
+    /// Finds the block of hexagons the pixel is in, then the hexagon with
+    /// the nearest centre
+    fn hexagonal_to_tile(&self, pixel: Vec2<f32>) -> Vec2<i32> {
+        let Vec2 { mut x, mut y } = pixel;
+        if self.stagger_x {
+            x -= if self.stagger_even {
+                self.tile_width
+            } else {
+                self.side_offset_x
+            };
+        } else {
+            y -= if self.stagger_even {
+                self.tile_height
+            } else {
+                self.side_offset_y
+            };
+        }
+
+        let block_width = self.column_width * 2.0;
+        let block_height = self.row_height * 2.0;
+        let mut tile_x = (x / block_width).floor() as i32;
+        let mut tile_y = (y / block_height).floor() as i32;
+        let rel_x = x - tile_x as f32 * block_width;
+        let rel_y = y - tile_y as f32 * block_height;
+
+        let (centres, offsets) = if self.stagger_x {
+            tile_x = tile_x * 2 + self.stagger_even as i32;
+            let left = self.side_x / 2.0;
+            let centre_x = left + self.column_width;
+            let centre_y = self.tile_height / 2.0;
+            (
+                [
+                    (left, centre_y),
+                    (centre_x, centre_y - self.row_height),
+                    (centre_x, centre_y + self.row_height),
+                    (centre_x + self.column_width, centre_y),
+                ],
+                [(0, 0), (1, -1), (1, 0), (2, 0)],
+            )
+        } else {
+            tile_y = tile_y * 2 + self.stagger_even as i32;
+            let top = self.side_y / 2.0;
+            let centre_x = self.tile_width / 2.0;
+            let centre_y = top + self.row_height;
+            (
+                [
+                    (centre_x, top),
+                    (centre_x - self.column_width, centre_y),
+                    (centre_x + self.column_width, centre_y),
+                    (centre_x, centre_y + self.row_height),
+                ],
+                [(0, 0), (-1, 1), (0, 1), (0, 2)],
+            )
+        };
+
+        let distance = |(x, y): (f32, f32)| {
+            (x - rel_x) * (x - rel_x) + (y - rel_y) * (y - rel_y)
+        };
+        let mut nearest = 0;
+        for i in 1..centres.len() {
+            if distance(centres[i]) < distance(centres[nearest]) {
+                nearest = i;
+            }
+        }
+        let (offset_x, offset_y) = offsets[nearest];
+        Vec2 {
+            x: tile_x + offset_x,
+            y: tile_y + offset_y,
+        }
+    }
+}
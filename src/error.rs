@@ -0,0 +1,192 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Everything that can go wrong while loading or saving a map.
+///
+/// Each variant records the file the problem was found in (`None` when
+/// loading from a string) and, where it applies, the JSON path to the
+/// offending value, eg; `layers[2].objects[5].polygon`. A path to the root
+/// of the document is empty.
+#[derive(Debug)]
+pub enum Error {
+    /// The map file itself could not be read, or written when saving
+    Io { file: PathBuf, source: io::Error },
+    /// The file is not valid JSON, or XML when reading Tiled's XML formats
+    Syntax {
+        file: Option<PathBuf>,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    /// The JSON is valid but doesn't match the Tiled format, eg; a missing
+    /// field or a value of the wrong type
+    Schema {
+        file: Option<PathBuf>,
+        json_path: String,
+        message: String,
+    },
+    /// Tile layer data is not valid base64 or doesn't hold whole GIDs
+    Decode {
+        file: Option<PathBuf>,
+        json_path: String,
+        message: String,
+    },
+    /// Compressed tile layer data could not be inflated, or the codec's
+    /// cargo feature is disabled
+    Decompress {
+        file: Option<PathBuf>,
+        json_path: String,
+        message: String,
+    },
+    /// A file referenced by the map, such as an external tileset, could
+    /// not be read. `file` and `json_path` locate the reference.
+    MissingReference {
+        file: Option<PathBuf>,
+        json_path: String,
+        reference: PathBuf,
+        source: io::Error,
+    },
+    /// The map could not be written as Tiled JSON, eg; a colour channel is
+    /// over 255 or tile data needs a codec whose feature is disabled
+    Serialize {
+        file: Option<PathBuf>,
+        json_path: String,
+        message: String,
+    },
+}
+
+impl Error {
+    /// The file in which the error was found
+    pub fn file(&self) -> Option<&Path> {
+        match self {
+            Error::Io { file, .. } => Some(file),
+            Error::Syntax { file, .. }
+            | Error::Schema { file, .. }
+            | Error::Decode { file, .. }
+            | Error::Decompress { file, .. }
+            | Error::MissingReference { file, .. }
+            | Error::Serialize { file, .. } => file.as_deref(),
+        }
+    }
+
+    /// The JSON path to the value at fault. `None` for errors which are
+    /// not tied to a value, ie; I/O and syntax errors.
+    pub fn json_path(&self) -> Option<&str> {
+        match self {
+            Error::Io { .. } | Error::Syntax { .. } => None,
+            Error::Schema { json_path, .. }
+            | Error::Decode { json_path, .. }
+            | Error::Decompress { json_path, .. }
+            | Error::MissingReference { json_path, .. }
+            | Error::Serialize { json_path, .. } => Some(json_path),
+        }
+    }
+
+    pub(crate) fn syntax(file: Option<&Path>, e: serde_json::Error) -> Self {
+        Error::Syntax {
+            file: file.map(Path::to_path_buf),
+            line: e.line(),
+            column: e.column(),
+            message: e.to_string(),
+        }
+    }
+
+    pub(crate) fn schema(
+        file: Option<&Path>,
+        e: serde_path_to_error::Error<serde_json::Error>,
+    ) -> Self {
+        Error::Schema {
+            file: file.map(Path::to_path_buf),
+            json_path: json_path(e.path()),
+            message: e.into_inner().to_string(),
+        }
+    }
+
+    pub(crate) fn serialize(
+        file: Option<&Path>,
+        e: serde_path_to_error::Error<serde_json::Error>,
+    ) -> Self {
+        Error::Serialize {
+            file: file.map(Path::to_path_buf),
+            json_path: json_path(e.path()),
+            message: e.into_inner().to_string(),
+        }
+    }
+}
+
+fn json_path(path: &serde_path_to_error::Path) -> String {
+    let json_path = path.to_string();
+    // The root is printed as "."
+    if json_path == "." {
+        String::new()
+    } else {
+        json_path
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let file = match self.file() {
+            Some(file) => file.display().to_string(),
+            None => "<string>".to_string(),
+        };
+        match self {
+            Error::Io { source, .. } => write!(f, "{}: {}", file, source),
+            Error::Syntax { message, .. } => {
+                write!(f, "{}: could not parse: {}", file, message)
+            }
+            Error::Schema {
+                json_path, message, ..
+            } => write!(f, "{}: {}: {}", file, json_path, message),
+            Error::Decode {
+                json_path, message, ..
+            } => write!(
+                f,
+                "{}: {}: could not decode tile data: {}",
+                file, json_path, message
+            ),
+            Error::Decompress {
+                json_path, message, ..
+            } => write!(
+                f,
+                "{}: {}: could not decompress tile data: {}",
+                file, json_path, message
+            ),
+            Error::MissingReference {
+                json_path,
+                reference,
+                source,
+                ..
+            } => write!(
+                f,
+                "{}: {}: could not load {}: {}",
+                file,
+                json_path,
+                reference.display(),
+                source
+            ),
+            Error::Serialize {
+                json_path, message, ..
+            } => write!(
+                f,
+                "{}: {}: could not write Tiled JSON: {}",
+                file, json_path, message
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. }
+            | Error::MissingReference { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
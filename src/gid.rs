@@ -0,0 +1,89 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// A global tile ID as stored in tile layer data and on tile objects.
+///
+/// Tiled keeps the flip and rotation state of a tile in the highest bits of
+/// the GID. `Gid` holds the raw value, so nothing is lost, and `id` returns
+/// it with those bits cleared - which is what should be used to find the
+/// tileset and tile. An `id` of 0 is an empty cell.
+#[derive(
+    Deserialize, Serialize, Debug, Default, PartialEq, Eq, Hash, Clone, Copy,
+)]
+#[serde(transparent)]
+pub struct Gid(pub u32);
+
+impl Gid {
+    pub const FLIPPED_HORIZONTALLY: u32 = 0x8000_0000;
+    pub const FLIPPED_VERTICALLY: u32 = 0x4000_0000;
+    /// Flipped over the top-left to bottom-right diagonal. For hexagonal
+    /// maps this is instead a 60 degree clockwise rotation.
+    pub const FLIPPED_DIAGONALLY: u32 = 0x2000_0000;
+    /// Rotated 120 degrees clockwise. Hexagonal maps only.
+    pub const ROTATED_HEXAGONAL_120: u32 = 0x1000_0000;
+    /// All of the flag bits
+    pub const FLAGS: u32 = Self::FLIPPED_HORIZONTALLY
+        | Self::FLIPPED_VERTICALLY
+        | Self::FLIPPED_DIAGONALLY
+        | Self::ROTATED_HEXAGONAL_120;
+
+    /// The value as it was in the file, flags included
+    pub fn raw(self) -> u32 {
+        self.0
+    }
+
+    /// The tile's GID with the flag bits cleared
+    pub fn id(self) -> u32 {
+        self.0 & !Self::FLAGS
+    }
+
+    /// True if this is an empty cell
+    pub fn is_empty(self) -> bool {
+        self.id() == 0
+    }
+
+    pub fn flip_h(self) -> bool {
+        self.0 & Self::FLIPPED_HORIZONTALLY != 0
+    }
+
+    pub fn flip_v(self) -> bool {
+        self.0 & Self::FLIPPED_VERTICALLY != 0
+    }
+
+    pub fn flip_d(self) -> bool {
+        self.0 & Self::FLIPPED_DIAGONALLY != 0
+    }
+
+    pub fn rotate_hex120(self) -> bool {
+        self.0 & Self::ROTATED_HEXAGONAL_120 != 0
+    }
+}
+
+impl From<u32> for Gid {
+    fn from(raw: u32) -> Self {
+        Gid(raw)
+    }
+}
+
+impl From<Gid> for u32 {
+    fn from(gid: Gid) -> Self {
+        gid.0
+    }
+}
+
+/// Compares the raw value, flags included
+impl PartialEq<u32> for Gid {
+    fn eq(&self, other: &u32) -> bool {
+        self.0 == *other
+    }
+}
+
+impl fmt::Display for Gid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
@@ -4,54 +4,365 @@
 
 use crate::{
     object::Object,
-    parsers::{parse_color, parse_data, parse_path, parse_property},
-    Color, TiledValue,
+    parsers::{
+        decode_data, one, parse_color, parse_compression, parse_data,
+        parse_optional_color, parse_path, parse_property,
+    },
+    writers::{encode_data, is_default, is_one, write_property},
+    Color, Gid, TileRect, TiledValue,
 };
-use serde::Deserialize;
+use serde::de::{Error, SeqAccess, Visitor};
+use serde::{ser, Deserialize, Deserializer, Serialize, Serializer};
 use std::collections::HashMap;
+use std::fmt;
 use std::path::PathBuf;
 
-#[derive(Deserialize, Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct TileLayer {
     /// Array of chunks (optional, generally infinite maps)
     pub chunks: Option<Vec<Chunk>>,
     /// Data consists of the global ID's of tiles making up this
-    /// layer of the map
-    #[serde(deserialize_with = "parse_data", default)]
-    pub data: Vec<u32>,
+    /// layer of the map, with their flip flags
+    pub data: Vec<Gid>,
+    /// How the data was stored in the file. The data is always
+    /// decoded by the time it reaches `data` or `Chunk::data`.
+    pub encoding: Encoding,
+    /// The compression used on base64 data, if any. Each codec is
+    /// only available with its cargo feature enabled.
+    pub compression: Option<Compression>,
     /// Row count. Same as map height for fixed-size maps.
     pub height: u32,
     /// Column count. Same as map width for fixed-size maps.
     pub width: u32,
 }
 
-#[derive(Deserialize, Debug, PartialEq, Clone)]
+/// A rectangular area of a `TileLayer`, from `TileLayer::region`.
+/// Positions are relative to the top-left of the area.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct TileRegion<'a> {
+    pub(crate) layer: &'a TileLayer,
+    pub(crate) area: TileRect,
+}
+
+impl<'a> TileRegion<'a> {
+    pub fn width(&self) -> u32 {
+        self.area.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.area.height
+    }
+
+    /// The area in the layer's tile positions
+    pub fn area(&self) -> &TileRect {
+        &self.area
+    }
+
+    /// Returns the GID at the position within the area, or `None` if it's
+    /// outside of the area or the layer
+    pub fn get(&self, x: i32, y: i32) -> Option<Gid> {
+        if x < 0
+            || y < 0
+            || x as u32 >= self.area.width
+            || y as u32 >= self.area.height
+        {
+            return None;
+        }
+        self.layer.get(self.area.x + x, self.area.y + y)
+    }
+
+    /// Iterates over the tiles in the area which aren't empty, as
+    /// `(x, y, gid)` within the area
+    pub fn iter_cells(&self) -> impl Iterator<Item = (i32, i32, Gid)> + 'a {
+        let region = *self;
+        (0..self.area.height as i32).flat_map(move |y| {
+            (0..region.area.width as i32).filter_map(move |x| {
+                region
+                    .get(x, y)
+                    .filter(|gid| !gid.is_empty())
+                    .map(|gid| (x, y, gid))
+            })
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for TileLayer {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Helper {
+            chunks: Option<Vec<RawChunk>>,
+            data: Option<RawData>,
+            #[serde(default)]
+            encoding: Encoding,
+            #[serde(deserialize_with = "parse_compression", default)]
+            compression: Option<Compression>,
+            height: u32,
+            width: u32,
+        }
+
+        let helper = Helper::deserialize(deserializer)?;
+        RawTileLayer {
+            chunks: helper.chunks,
+            data: helper.data,
+            encoding: helper.encoding,
+            compression: helper.compression,
+            height: helper.height,
+            width: helper.width,
+        }
+        .decode()
+    }
+}
+
+impl Serialize for TileLayer {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        #[derive(Serialize)]
+        struct ChunkHelper<'a> {
+            data: EncodedData<'a>,
+            height: u32,
+            width: u32,
+            x: i32,
+            y: i32,
+        }
+
+        #[derive(Serialize)]
+        struct Helper<'a> {
+            #[serde(skip_serializing_if = "Option::is_none")]
+            chunks: Option<Vec<ChunkHelper<'a>>>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            data: Option<EncodedData<'a>>,
+            #[serde(skip_serializing_if = "is_default")]
+            encoding: Encoding,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            compression: Option<Compression>,
+            height: u32,
+            width: u32,
+        }
+
+        let encoded = |gids| EncodedData {
+            gids,
+            encoding: &self.encoding,
+            compression: self.compression,
+        };
+        let chunks = self.chunks.as_ref().map(|chunks| {
+            chunks
+                .iter()
+                .map(|chunk| ChunkHelper {
+                    data: encoded(&chunk.data),
+                    height: chunk.height,
+                    width: chunk.width,
+                    x: chunk.x,
+                    y: chunk.y,
+                })
+                .collect()
+        });
+        // Infinite maps keep their tiles only in chunks
+        let data = if self.data.is_empty() && chunks.is_some() {
+            None
+        } else {
+            Some(encoded(&self.data))
+        };
+
+        Helper {
+            chunks,
+            data,
+            encoding: self.encoding.clone(),
+            compression: self.compression,
+            height: self.height,
+            width: self.width,
+        }
+        .serialize(serializer)
+    }
+}
+
+/// Tile data to be written with a layer's encoding and compression. The
+/// encoding happens while serializing so any error is located at the data.
+struct EncodedData<'a> {
+    gids: &'a [Gid],
+    encoding: &'a Encoding,
+    compression: Option<Compression>,
+}
+
+impl<'a> Serialize for EncodedData<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self.encoding {
+            Encoding::Csv => self.gids.serialize(serializer),
+            Encoding::Base64 => {
+                let encoded = encode_data(self.gids, self.compression.as_ref())
+                    .map_err(ser::Error::custom)?;
+                serializer.serialize_str(&encoded)
+            }
+        }
+    }
+}
+
+/// Tile data which may still be encoded
+enum RawData {
+    Gids(Vec<Gid>),
+    Encoded(String),
+}
+
+impl<'de> Deserialize<'de> for RawData {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct SomeVisitor;
+
+        impl<'de> Visitor<'de> for SomeVisitor {
+            type Value = RawData;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("an array of u32 or a base64 string")
+            }
+
+            fn visit_str<E>(self, value: &str) -> Result<RawData, E>
+            where
+                E: Error,
+            {
+                Ok(RawData::Encoded(value.to_string()))
+            }
+
+            fn visit_seq<S>(self, mut seq: S) -> Result<RawData, S::Error>
+            where
+                S: SeqAccess<'de>,
+            {
+                let mut v = Vec::<Gid>::new();
+                while let Some(val) = seq.next_element::<Gid>()? {
+                    v.push(val);
+                }
+                Ok(RawData::Gids(v))
+            }
+        }
+
+        deserializer.deserialize_any(SomeVisitor)
+    }
+}
+
+#[derive(Deserialize)]
+struct RawChunk {
+    data: Option<RawData>,
+    height: u32,
+    width: u32,
+    x: i32,
+    y: i32,
+}
+
+/// A `TileLayer` as it is in the file, before any data is decoded
+struct RawTileLayer {
+    chunks: Option<Vec<RawChunk>>,
+    data: Option<RawData>,
+    encoding: Encoding,
+    compression: Option<Compression>,
+    height: u32,
+    width: u32,
+}
+
+impl RawTileLayer {
+    fn decode<E: Error>(self) -> Result<TileLayer, E> {
+        let compression = self.compression.as_ref();
+        let decode = |data: Option<RawData>| match data {
+            Some(RawData::Gids(gids)) => Ok(gids),
+            Some(RawData::Encoded(s)) => {
+                decode_data(&s, compression).map_err(E::custom)
+            }
+            None => Ok(Vec::new()),
+        };
+
+        let chunks = match self.chunks {
+            Some(chunks) => {
+                let mut decoded = Vec::with_capacity(chunks.len());
+                for chunk in chunks {
+                    decoded.push(Chunk {
+                        data: decode(chunk.data)?,
+                        height: chunk.height,
+                        width: chunk.width,
+                        x: chunk.x,
+                        y: chunk.y,
+                    });
+                }
+                Some(decoded)
+            }
+            None => None,
+        };
+
+        Ok(TileLayer {
+            chunks,
+            data: decode(self.data)?,
+            encoding: self.encoding,
+            compression: self.compression,
+            height: self.height,
+            width: self.width,
+        })
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
 pub struct ObjectGroup {
     /// `TopDown` (default) or `Index`
-    #[serde(rename(deserialize = "draworder"), default)]
+    #[serde(rename = "draworder", default)]
     pub draw_order: DrawOrder,
     /// The array of `Object` in this layer
     #[serde(default)]
     pub objects: Vec<Object>,
+    /// The colour the objects are drawn in, if not Tiled's default grey
+    #[serde(
+        deserialize_with = "parse_optional_color",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub color: Option<Color>,
 }
 
-/// Contains a file path to an image plus a mix colour
-#[derive(Deserialize, Debug, PartialEq, Clone)]
+/// Contains a file path to an image plus a mix colour. The image is tinted
+/// by the `tint_color` of the `Layer`.
+///
+/// See `Map::image_layer_rects` for where to draw the image.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
 pub struct ImageLayer {
     /// Image used by this layer
     #[serde(deserialize_with = "parse_path", default)]
     pub image: PathBuf,
+    /// Width of the image in pixels, or 0 if the map doesn't say
     #[serde(
-        rename(deserialize = "transparentcolor"),
+        rename = "imagewidth",
+        default,
+        skip_serializing_if = "is_default"
+    )]
+    pub image_width: u32,
+    /// Height of the image in pixels, or 0 if the map doesn't say
+    #[serde(
+        rename = "imageheight",
+        default,
+        skip_serializing_if = "is_default"
+    )]
+    pub image_height: u32,
+    /// Whether the image is repeated along the x axis (default: false)
+    #[serde(rename = "repeatx", default, skip_serializing_if = "is_default")]
+    pub repeat_x: bool,
+    /// Whether the image is repeated along the y axis (default: false)
+    #[serde(rename = "repeaty", default, skip_serializing_if = "is_default")]
+    pub repeat_y: bool,
+    #[serde(
+        rename = "transparentcolor",
         deserialize_with = "parse_color",
-        default
+        default,
+        skip_serializing_if = "is_default"
     )]
     /// Defaults to 0,0,0,0 (rgba)
     pub transparent_color: Color,
 }
 
 /// Used to group layers if required
-#[derive(Deserialize, Debug, PartialEq, Clone)]
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
 pub struct Group {
     pub layers: Vec<Layer>,
 }
@@ -76,8 +387,8 @@ pub struct Group {
 ///     }
 /// }
 /// ```
-#[derive(Deserialize, Debug, PartialEq, Clone)]
-#[serde(rename_all(deserialize = "lowercase"), tag = "type")]
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+#[serde(rename_all = "lowercase", tag = "type")]
 pub enum LayerType {
     TileLayer(TileLayer),
     ObjectGroup(ObjectGroup),
@@ -89,38 +400,251 @@ pub enum LayerType {
 ///
 /// Layers have sub-types such as (enum) `LayerType::TileLayer(TileLayer)`
 /// which contains the data for that sub-type.
-#[derive(Deserialize, Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct Layer {
+    /// Incremental id - unique across all layers
+    pub id: Option<u32>,
     pub name: String,
     /// Horizontal layer offset in pixels (default: 0)
-    #[serde(rename(deserialize = "offsetx"), default)]
     pub offset_x: f32,
     /// Vertical layer offset in pixels (default: 0)
-    #[serde(rename(deserialize = "offsety"), default)]
     pub offset_y: f32,
     /// Value between 0 and 1
     pub opacity: f32,
-    #[serde(deserialize_with = "parse_property", default)]
+    /// How fast the layer scrolls horizontally relative to the view. 1 is
+    /// the same speed, 0 is fixed to the view. Groups multiply this for
+    /// their layers.
+    pub parallax_x: f32,
+    /// How fast the layer scrolls vertically relative to the view
+    pub parallax_y: f32,
+    /// A colour multiplied with the layer's tiles, images or objects when
+    /// drawn, which is white if not given. Groups multiply this for their
+    /// layers.
+    pub tint_color: Option<Color>,
+    /// The class of the layer, as defined in Tiled's custom types
+    pub class: String,
+    /// Whether the layer is locked in the editor (default: false)
+    pub locked: bool,
     pub properties: HashMap<String, TiledValue>,
-    #[serde(flatten)]
     /// The `LayerType` object also contains the data relating to the type
     pub layer_type: LayerType,
     /// Horizontal layer offset in tiles. Always 0.
-    #[serde(default)]
     pub x: i32,
     /// Vertical layer offset in tiles. Always 0.
-    #[serde(default)]
     pub y: i32,
 
     pub visible: bool,
 }
 
+impl<'de> Deserialize<'de> for Layer {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "lowercase")]
+        enum Kind {
+            TileLayer,
+            ObjectGroup,
+            ImageLayer,
+            Group,
+        }
+
+        #[derive(Deserialize)]
+        struct Helper {
+            id: Option<u32>,
+            name: String,
+            #[serde(rename = "offsetx", default)]
+            offset_x: f32,
+            #[serde(rename = "offsety", default)]
+            offset_y: f32,
+            opacity: f32,
+            #[serde(rename = "parallaxx", default = "one")]
+            parallax_x: f32,
+            #[serde(rename = "parallaxy", default = "one")]
+            parallax_y: f32,
+            #[serde(
+                rename = "tintcolor",
+                deserialize_with = "parse_optional_color",
+                default
+            )]
+            tint_color: Option<Color>,
+            #[serde(default)]
+            class: String,
+            #[serde(default)]
+            locked: bool,
+            #[serde(deserialize_with = "parse_property", default)]
+            properties: HashMap<String, TiledValue>,
+            #[serde(rename = "type")]
+            kind: Kind,
+            #[serde(default)]
+            x: i32,
+            #[serde(default)]
+            y: i32,
+            visible: bool,
+            // TileLayer
+            chunks: Option<Vec<RawChunk>>,
+            data: Option<RawData>,
+            #[serde(default)]
+            encoding: Encoding,
+            #[serde(deserialize_with = "parse_compression", default)]
+            compression: Option<Compression>,
+            height: Option<u32>,
+            width: Option<u32>,
+            // ObjectGroup
+            #[serde(rename = "draworder", default)]
+            draw_order: DrawOrder,
+            #[serde(default)]
+            objects: Vec<Object>,
+            #[serde(deserialize_with = "parse_optional_color", default)]
+            color: Option<Color>,
+            // ImageLayer
+            #[serde(deserialize_with = "parse_path", default)]
+            image: PathBuf,
+            #[serde(rename = "imagewidth", default)]
+            image_width: u32,
+            #[serde(rename = "imageheight", default)]
+            image_height: u32,
+            #[serde(rename = "repeatx", default)]
+            repeat_x: bool,
+            #[serde(rename = "repeaty", default)]
+            repeat_y: bool,
+            #[serde(
+                rename = "transparentcolor",
+                deserialize_with = "parse_color",
+                default
+            )]
+            transparent_color: Color,
+            // Group
+            layers: Option<Vec<Layer>>,
+        }
+
+        let helper = Helper::deserialize(deserializer)?;
+        let layer_type = match helper.kind {
+            Kind::TileLayer => LayerType::TileLayer(
+                RawTileLayer {
+                    chunks: helper.chunks,
+                    data: helper.data,
+                    encoding: helper.encoding,
+                    compression: helper.compression,
+                    height: helper
+                        .height
+                        .ok_or_else(|| D::Error::missing_field("height"))?,
+                    width: helper
+                        .width
+                        .ok_or_else(|| D::Error::missing_field("width"))?,
+                }
+                .decode()?,
+            ),
+            Kind::ObjectGroup => LayerType::ObjectGroup(ObjectGroup {
+                draw_order: helper.draw_order,
+                objects: helper.objects,
+                color: helper.color,
+            }),
+            Kind::ImageLayer => LayerType::ImageLayer(ImageLayer {
+                image: helper.image,
+                image_width: helper.image_width,
+                image_height: helper.image_height,
+                repeat_x: helper.repeat_x,
+                repeat_y: helper.repeat_y,
+                transparent_color: helper.transparent_color,
+            }),
+            Kind::Group => LayerType::Group {
+                layers: helper
+                    .layers
+                    .ok_or_else(|| D::Error::missing_field("layers"))?,
+            },
+        };
+
+        Ok(Layer {
+            id: helper.id,
+            name: helper.name,
+            offset_x: helper.offset_x,
+            offset_y: helper.offset_y,
+            opacity: helper.opacity,
+            parallax_x: helper.parallax_x,
+            parallax_y: helper.parallax_y,
+            tint_color: helper.tint_color,
+            class: helper.class,
+            locked: helper.locked,
+            properties: helper.properties,
+            layer_type,
+            x: helper.x,
+            y: helper.y,
+            visible: helper.visible,
+        })
+    }
+}
+
+impl Serialize for Layer {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        #[derive(Serialize)]
+        struct Helper<'a> {
+            #[serde(skip_serializing_if = "Option::is_none")]
+            id: Option<u32>,
+            name: &'a str,
+            #[serde(rename = "offsetx", skip_serializing_if = "is_default")]
+            offset_x: f32,
+            #[serde(rename = "offsety", skip_serializing_if = "is_default")]
+            offset_y: f32,
+            opacity: f32,
+            #[serde(rename = "parallaxx", skip_serializing_if = "is_one")]
+            parallax_x: f32,
+            #[serde(rename = "parallaxy", skip_serializing_if = "is_one")]
+            parallax_y: f32,
+            #[serde(
+                rename = "tintcolor",
+                skip_serializing_if = "Option::is_none"
+            )]
+            tint_color: &'a Option<Color>,
+            #[serde(skip_serializing_if = "str::is_empty")]
+            class: &'a str,
+            #[serde(skip_serializing_if = "is_default")]
+            locked: bool,
+            #[serde(
+                serialize_with = "write_property",
+                skip_serializing_if = "HashMap::is_empty"
+            )]
+            properties: &'a HashMap<String, TiledValue>,
+            // Adds `type` along with the fields of the variant
+            #[serde(flatten)]
+            layer_type: &'a LayerType,
+            x: i32,
+            y: i32,
+            visible: bool,
+        }
+
+        Helper {
+            id: self.id,
+            name: &self.name,
+            offset_x: self.offset_x,
+            offset_y: self.offset_y,
+            opacity: self.opacity,
+            parallax_x: self.parallax_x,
+            parallax_y: self.parallax_y,
+            tint_color: &self.tint_color,
+            class: &self.class,
+            locked: self.locked,
+            properties: &self.properties,
+            layer_type: &self.layer_type,
+            x: self.x,
+            y: self.y,
+            visible: self.visible,
+        }
+        .serialize(serializer)
+    }
+}
+
 /// Chunks are used to store the tile layer data for infinite maps
-#[derive(Deserialize, Debug, PartialEq, Eq, Clone)]
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone)]
 pub struct Chunk {
-    /// Array of unsigned int (GIDs) or base64-encoded data
+    /// Array of unsigned int (GIDs) or base64-encoded data. Compressed
+    /// data is only handled when the chunk is part of a `TileLayer`.
     #[serde(deserialize_with = "parse_data", default)]
-    pub data: Vec<u32>,
+    pub data: Vec<Gid>,
     pub height: u32,
     pub width: u32,
     pub x: i32,
@@ -128,15 +652,48 @@ pub struct Chunk {
 }
 
 /// Can be `TopDown` (default) or `Index`. Applies to `ObjectGroup` only.
-#[derive(Deserialize, Debug, PartialEq, Eq, Clone)]
-#[serde(rename_all(deserialize = "lowercase"))]
+#[derive(Deserialize, Serialize, Debug, Default, PartialEq, Eq, Clone)]
+#[serde(rename_all = "lowercase")]
 pub enum DrawOrder {
+    #[default]
     TopDown,
     Index,
 }
 
-impl Default for DrawOrder {
-    fn default() -> Self {
-        DrawOrder::TopDown
+/// How `TileLayer` data is stored: a plain array of GIDs (`Csv`, the
+/// default) or a base64 string of little-endian u32s
+#[derive(Deserialize, Serialize, Debug, Default, PartialEq, Eq, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    #[default]
+    Csv,
+    Base64,
+}
+
+/// Compression applied to base64 encoded `TileLayer` data
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Compression {
+    Zlib,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// The name Tiled uses for this compression
+    pub fn name(&self) -> &'static str {
+        match self {
+            Compression::Zlib => "zlib",
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+        }
+    }
+}
+
+impl Serialize for Compression {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.name())
     }
 }
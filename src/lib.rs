@@ -24,6 +24,7 @@
 //! # use tiled_json_rs as tiled;
 //! # let map = tiled::Map::load_from_file(&PathBuf::from("tests/data/csv.json")).unwrap();
 //! for tileset in &map.tile_sets {
+//!     let tileset = tileset.internal();
 //!     let name = tileset.name.clone();
 //!     let mut path = PathBuf::from("assets");
 //!     path.push(tileset.image.clone());
@@ -59,34 +60,80 @@
 //! render_layers(&map.layers);
 //! ```
 //!
+//! Or, to have the offsets, opacity and the rest of each group applied,
+//! walk the layers with `Map::iter_layers`:
+//!
+//! ```
+//! # use std::path::PathBuf;
+//! # use tiled_json_rs as tiled;
+//! # let map = tiled::Map::load_from_file(&PathBuf::from("tests/data/csv.json")).unwrap();
+//! for info in map.iter_layers().filter(|info| info.visible) {
+//!     let group = info.path.join("/");
+//!     let (x, y, opacity) = (info.offset.x, info.offset.y, info.opacity);
+//!     //draw(info.layer, x, y, opacity);
+//! }
+//! ```
+//!
 //! ## Info
 //!
 //! Tiled can export maps as JSON files. To do so, simply select “File > Export As”
 //! and select the JSON file type. You can export json from the command line with
 //! the `--export-map` option.
 //!
+//! With the `xml` feature, Tiled's own TMX maps, TSX tilesets and TX templates
+//! can be loaded directly, in to the same types. The format is detected from
+//! the contents, so `Map::load_from_file` takes either, as do external tileset
+//! and template references. Maps are always saved as JSON.
+//!
+//! Base64 tile data compressed with zlib, gzip or zstd is inflated while
+//! parsing. Each codec is enabled by the cargo feature of the same name, all
+//! of which are on by default.
+//!
+//! Everything a map references is read through a `ResourceLoader`. The
+//! `load_*` functions use the filesystem; `Map::load_with` takes any other
+//! loader, such as `InMemory` for embedded assets or one reading an archive.
+//!
+//! A `Map` can be written back out with `Map::to_string` or
+//! `Map::save_to_file`. Every type also implements `Serialize`, producing
+//! the same JSON Tiled does.
+//!
 //! Notes:
 //! - GID for tiles starts at 1 with 0 reserved for *empty* tile
+//! - GIDs are kept as `Gid`, which still carries the flip flags Tiled stores
+//!   in the high bits. The lookup helpers on `Map` and `TileSet` strip them.
 //! - Local Id starts at 0 for `TileSet`, and only applies to `TileSet`
 //! - Doc comments are only provided where clarification may be useful. In general things
 //!   should be named well enough that intention is self-describing.
 //!
-use serde::Deserialize;
+use serde::{Deserialize, Serialize, Serializer};
 
+mod coords;
+mod error;
+mod gid;
 mod layer;
+mod load;
 mod map;
 mod object;
+mod resource;
 mod tile_set;
+mod tree;
 mod utils;
 mod wangs;
 
+pub use error::Error;
+pub use gid::Gid;
 pub use layer::*;
 pub use map::*;
 pub use object::*;
+pub use resource::{FileSystem, InMemory, ResourceLoader};
 pub use tile_set::*;
+pub use tree::{LayerInfo, Layers};
 pub use wangs::*;
 
 mod parsers;
+mod writers;
+#[cfg(feature = "xml")]
+mod xml;
 
 use parsers::parse_color;
 
@@ -94,8 +141,8 @@ use parsers::parse_color;
 ///
 /// It contains the basic types that Tiled uses.
 /// This is generally used in the properties of layers, tiles, and objects.
-#[derive(Deserialize, Debug, PartialEq, Clone)]
-#[serde(rename_all(deserialize = "lowercase"), tag = "type", content = "value")]
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+#[serde(rename_all = "lowercase", tag = "type", content = "value")]
 pub enum TiledValue {
     Bool(bool),
     Float(f32),
@@ -109,12 +156,23 @@ pub enum TiledValue {
 
 
 /// A simple representation of a 2d Vector to pass coords around
-#[derive(Deserialize, Debug, PartialEq, Clone)]
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, Copy)]
 pub struct Vec2<T> {
     pub x: T,
     pub y: T,
 }
 
+impl Vec2<f32> {
+    /// Rounds to whole pixels, such as to use polygon points where they
+    /// were once whole numbers
+    pub fn round(self) -> Vec2<i32> {
+        Vec2 {
+            x: self.x.round() as i32,
+            y: self.y.round() as i32,
+        }
+    }
+}
+
 /// An RGBA representation of colours
 ///
 /// Order of colours in the tuple follow the Red-Green-Blue-Alpha pattern
@@ -143,14 +201,62 @@ impl Color {
     }
 }
 
+/// Written as Tiled does, `#RRGGBB` or `#AARRGGBB` when not opaque
+impl Serialize for Color {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        writers::write_color(self, serializer)
+    }
+}
+
 /// Used to provide the location and dimensions of the required
-/// tile on the tiles tileset image.
+/// tile on the tiles tileset image, or of an area of tiles in a layer.
 ///
 /// Functionally similar to SDL2 Rect.
-#[derive(Debug, Default, PartialEq, Clone)]
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
 pub struct TileRect {
     pub x: i32,
     pub y: i32,
     pub width: u32,
     pub height: u32,
 }
+
+/// An area in pixels, such as a view of the map
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct Rect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+/// Everything about the tile a GID refers to, borrowed from the `Map`.
+/// Returned by `Map::tile_info`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct TileInfo<'a> {
+    /// The GID looked up, with its flip flags
+    pub gid: Gid,
+    pub tile_set: &'a Internal,
+    /// The ID of the tile within `tile_set`
+    pub local_id: u32,
+    /// The image the tile is drawn from. Only `None` for a tile of an image
+    /// collection without an image.
+    pub image: Option<&'a std::path::Path>,
+    /// Where the tile is on `image`
+    pub rect: Option<TileRect>,
+    pub flip_h: bool,
+    pub flip_v: bool,
+    pub flip_d: bool,
+    /// Rotated by 120 degrees, which only hexagonal maps use
+    pub rotate_hex120: bool,
+    /// The tile's extra details, if it has any. The fields below are taken
+    /// from it.
+    pub tile: Option<&'a Tile>,
+    pub properties: Option<&'a std::collections::HashMap<String, TiledValue>>,
+    pub tile_type: Option<&'a str>,
+    pub animation: Option<&'a [Frame]>,
+    /// The tile's collision shapes
+    pub object_group: Option<&'a ObjectGroup>,
+}
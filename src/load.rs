@@ -0,0 +1,499 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! The steps shared by everything that is loaded from a file. The JSON is
+//! first read in to a `serde_json::Value` so that encoded tile data can be
+//! decoded with its location known, and is then deserialized with the path
+//! to each value tracked for error reporting. XML documents are converted
+//! to the same `Value` first.
+//!
+//! Object templates are also merged in to the `Value`, so a template
+//! instance is deserialized the same as any other object. As in Tiled, the
+//! template provides whatever the instance doesn't set.
+//!
+//! The types which could be several things, such as `Layer`, `Object` and
+//! `TileSet`, are each read through one flat helper struct rather than an
+//! untagged enum or a flattened, tagged one. Those make serde buffer the
+//! value, which loses the location of any error within it.
+
+use crate::{
+    error::Error,
+    object::INHERITED,
+    parsers::{decode_data, DataError},
+    Compression, Gid, Map, ResourceLoader, TileSet,
+};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::path::{Component, Path, PathBuf};
+
+/// Parses a JSON document, or a TMX, TSX or TX document with the `xml`
+/// feature. `file` is the file it was read from, if any. Object templates
+/// are read by `loader` relative to it; without it they're left
+/// unresolved.
+pub(crate) fn parse<T: DeserializeOwned>(
+    bytes: &[u8],
+    file: Option<&Path>,
+    loader: &dyn ResourceLoader,
+) -> Result<T, Error> {
+    let mut value = read_value(bytes, file)?;
+    decode_layers(&mut value, "", file)?;
+    if let Some(file) = file {
+        let base_dir = file.parent().unwrap_or_else(|| Path::new(""));
+        let mut templates = Templates {
+            base_dir,
+            file,
+            loader,
+            tile_sets: external_tile_sets(&value, base_dir, file)?,
+            loaded: HashMap::new(),
+        };
+        templates.resolve(&mut value, "")?;
+    }
+    serde_path_to_error::deserialize(value).map_err(|e| Error::schema(file, e))
+}
+
+/// Reads a file referenced by another, such as a tileset or template.
+/// `file` and `json_path` locate the reference for the error if it can't
+/// be read.
+pub(crate) fn read_reference(
+    loader: &dyn ResourceLoader,
+    path: &Path,
+    file: Option<&Path>,
+    json_path: String,
+) -> Result<Vec<u8>, Error> {
+    loader.read(path).map_err(|source| Error::MissingReference {
+        file: file.map(Path::to_path_buf),
+        json_path,
+        reference: path.to_path_buf(),
+        source,
+    })
+}
+
+/// Replaces each `TileSet::External` of the map with the tileset it
+/// references. `file` is the map's own file, if known.
+pub(crate) fn resolve_tile_sets(
+    map: &mut Map,
+    base_dir: &Path,
+    file: Option<&Path>,
+    loader: &dyn ResourceLoader,
+) -> Result<(), Error> {
+    for (i, tile_set) in map.tile_sets.iter_mut().enumerate() {
+        if let TileSet::External(external) = tile_set {
+            let json_path = format!("tilesets[{}]", i);
+            let internal =
+                external.load_referenced(base_dir, file, json_path, loader)?;
+            *tile_set = TileSet::Internal(internal);
+        }
+    }
+    Ok(())
+}
+
+fn read_value(bytes: &[u8], file: Option<&Path>) -> Result<Value, Error> {
+    if is_xml(bytes) {
+        xml_value(bytes, file)
+    } else {
+        serde_json::from_slice(bytes).map_err(|e| Error::syntax(file, e))
+    }
+}
+
+/// JSON can't start with '<', so there's no need to trust file extensions
+fn is_xml(bytes: &[u8]) -> bool {
+    let bytes = bytes.strip_prefix(b"\xef\xbb\xbf").unwrap_or(bytes);
+    bytes.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'<')
+}
+
+#[cfg(feature = "xml")]
+fn xml_value(bytes: &[u8], file: Option<&Path>) -> Result<Value, Error> {
+    let text = std::str::from_utf8(bytes).map_err(|e| {
+        let valid = &bytes[..e.valid_up_to()];
+        let line = valid.iter().filter(|&&b| b == b'\n').count() + 1;
+        let line_start =
+            valid.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+        Error::Syntax {
+            file: file.map(Path::to_path_buf),
+            line,
+            column: valid.len() - line_start + 1,
+            message: e.to_string(),
+        }
+    })?;
+    crate::xml::to_json(text).map_err(|e| {
+        let position = e.pos();
+        Error::Syntax {
+            file: file.map(Path::to_path_buf),
+            line: position.row as usize,
+            column: position.col as usize,
+            message: e.to_string(),
+        }
+    })
+}
+
+#[cfg(not(feature = "xml"))]
+fn xml_value(_bytes: &[u8], file: Option<&Path>) -> Result<Value, Error> {
+    Err(Error::Syntax {
+        file: file.map(Path::to_path_buf),
+        line: 1,
+        column: 1,
+        message: "TMX, TSX and TX files require the `xml` feature".into(),
+    })
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+/// Decodes the base64 `data` of every tile layer, and of their chunks, in
+/// the `layers` array of `value`, recursing in to groups
+fn decode_layers(
+    value: &mut Value,
+    path: &str,
+    file: Option<&Path>,
+) -> Result<(), Error> {
+    let layers = match value.get_mut("layers") {
+        Some(Value::Array(layers)) => layers,
+        _ => return Ok(()),
+    };
+    let path = join(path, "layers");
+
+    for (i, layer) in layers.iter_mut().enumerate() {
+        let path = format!("{}[{}]", path, i);
+        if layer.get("type").and_then(Value::as_str) == Some("tilelayer") {
+            // An unknown compression is left for the deserializer to report
+            let compression = match layer.get("compression") {
+                Some(Value::String(s)) => match s.as_str() {
+                    "zlib" => Some(Compression::Zlib),
+                    "gzip" => Some(Compression::Gzip),
+                    "zstd" => Some(Compression::Zstd),
+                    _ => None,
+                },
+                _ => None,
+            };
+
+            if let Some(data) = layer.get_mut("data") {
+                decode_value(data, compression, &join(&path, "data"), file)?;
+            }
+            if let Some(Value::Array(chunks)) = layer.get_mut("chunks") {
+                for (j, chunk) in chunks.iter_mut().enumerate() {
+                    if let Some(data) = chunk.get_mut("data") {
+                        let path = format!("{}.chunks[{}].data", path, j);
+                        decode_value(data, compression, &path, file)?;
+                    }
+                }
+            }
+        }
+        decode_layers(layer, &path, file)?;
+    }
+    Ok(())
+}
+
+fn decode_value(
+    data: &mut Value,
+    compression: Option<Compression>,
+    path: &str,
+    file: Option<&Path>,
+) -> Result<(), Error> {
+    let encoded = match data {
+        Value::String(encoded) => encoded,
+        _ => return Ok(()),
+    };
+    let gids = decode_data(encoded, compression.as_ref()).map_err(|e| {
+        let file = file.map(Path::to_path_buf);
+        let json_path = path.to_string();
+        match e {
+            DataError::Decode(message) => Error::Decode {
+                file,
+                json_path,
+                message,
+            },
+            DataError::Decompress(message) => Error::Decompress {
+                file,
+                json_path,
+                message,
+            },
+        }
+    })?;
+    *data = Value::Array(
+        gids.into_iter().map(|gid| Value::from(gid.raw())).collect(),
+    );
+    Ok(())
+}
+
+/// The first GID and normalised path of each external tileset listed in
+/// the `tilesets` of `value`, which was read from `file`
+fn external_tile_sets(
+    value: &Value,
+    base_dir: &Path,
+    file: &Path,
+) -> Result<Vec<(u32, PathBuf)>, Error> {
+    let tile_sets = match value.get("tilesets") {
+        Some(Value::Array(tile_sets)) => tile_sets,
+        _ => return Ok(Vec::new()),
+    };
+    let mut external = Vec::new();
+    for (i, tile_set) in tile_sets.iter().enumerate() {
+        let json_path = format!("tilesets[{}].firstgid", i);
+        let first_gid = first_gid(tile_set, file, json_path)?;
+        let source = tile_set.get("source").and_then(Value::as_str);
+        if let (Some(first_gid), Some(source)) = (first_gid, source) {
+            external.push((first_gid, normalise(&base_dir.join(source))));
+        }
+    }
+    Ok(external)
+}
+
+/// The `firstgid` of a tileset, if it has one. A number too large to be a
+/// GID is an error at `json_path` in `file`.
+fn first_gid(
+    tile_set: &Value,
+    file: &Path,
+    json_path: String,
+) -> Result<Option<u32>, Error> {
+    let first_gid = match tile_set.get("firstgid").and_then(Value::as_u64) {
+        Some(first_gid) => first_gid,
+        None => return Ok(None),
+    };
+    u32::try_from(first_gid)
+        .map(Some)
+        .map_err(|_| Error::Schema {
+            file: Some(file.to_path_buf()),
+            json_path,
+            message: "`firstgid` is too large to be a GID".into(),
+        })
+}
+
+/// Removes `.` and `..` from a path without touching the filesystem, so
+/// that two relative paths to the same file compare equal
+pub(crate) fn normalise(path: &Path) -> PathBuf {
+    let mut normal = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normal.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normal.pop();
+                }
+                _ => normal.push(component),
+            },
+            _ => normal.push(component),
+        }
+    }
+    normal
+}
+
+/// The fields of an object which give its shape. An instance setting any
+/// of them replaces the template's shape.
+const SHAPES: [&str; 5] = ["ellipse", "point", "polygon", "polyline", "text"];
+
+/// A template as used when merging it in to its instances
+struct Template {
+    object: Value,
+    /// The first GID and normalised path of the tileset the template's
+    /// `gid` belongs to, if it is a tile object
+    tile_set: Option<(u32, PathBuf)>,
+}
+
+/// Merges object templates in to the objects which use them
+struct Templates<'a> {
+    /// Template paths are relative to the directory of the file
+    /// referencing them
+    base_dir: &'a Path,
+    file: &'a Path,
+    loader: &'a dyn ResourceLoader,
+    /// The external tilesets of the map, which a template's tileset is
+    /// looked up in to translate its `gid`
+    tile_sets: Vec<(u32, PathBuf)>,
+    /// Each template by path, as most are used many times
+    loaded: HashMap<PathBuf, Template>,
+}
+
+impl<'a> Templates<'a> {
+    /// Finds every object with a `template` in `value`, wherever it is, eg;
+    /// in an object layer or the collision shapes of a tileset's tiles
+    fn resolve(&mut self, value: &mut Value, path: &str) -> Result<(), Error> {
+        match value {
+            Value::Object(fields) => {
+                for (key, child) in fields.iter_mut() {
+                    let path = join(path, key);
+                    if key == "objects" {
+                        if let Value::Array(objects) = child {
+                            for (i, object) in objects.iter_mut().enumerate() {
+                                let path = format!("{}[{}]", path, i);
+                                self.apply(object, &path)?;
+                            }
+                        }
+                    }
+                    self.resolve(child, &path)?;
+                }
+            }
+            Value::Array(items) => {
+                for (i, item) in items.iter_mut().enumerate() {
+                    // Skips past tile data without building a path per GID
+                    if item.is_object() || item.is_array() {
+                        self.resolve(item, &format!("{}[{}]", path, i))?;
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Fills in the fields an object doesn't set from its template. The
+    /// properties of both are merged, with the instance's value kept for a
+    /// name in both, and the template's `gid` is translated to the map's
+    /// GIDs for its tileset. What the template filled in is kept with the
+    /// object, so that saving it can leave those fields to the template.
+    fn apply(&mut self, object: &mut Value, path: &str) -> Result<(), Error> {
+        let instance = match object {
+            Value::Object(instance) => instance,
+            _ => return Ok(()),
+        };
+        // Anything other than a string is left for the deserializer
+        let template = match instance.get("template") {
+            Some(Value::String(template)) => self.base_dir.join(template),
+            _ => return Ok(()),
+        };
+        let json_path = join(path, "template");
+        let file = self.file;
+        let template = Self::load(
+            &mut self.loaded,
+            self.loader,
+            file,
+            template,
+            json_path.clone(),
+        )?;
+        let fields = match &template.object {
+            Value::Object(fields) => fields,
+            _ => return Ok(()),
+        };
+        let has_shape = SHAPES.iter().any(|&key| instance.contains_key(key));
+        let mut inherited = serde_json::Map::new();
+
+        for (key, value) in fields {
+            let key = key.as_str();
+            if key == "id" || (has_shape && SHAPES.contains(&key)) {
+                continue;
+            }
+            match (key, instance.get_mut(key)) {
+                ("properties", Some(Value::Array(properties))) => {
+                    let added = merge_properties(properties, value);
+                    if !added.is_empty() {
+                        inherited.insert(key.into(), Value::Array(added));
+                    }
+                }
+                (_, Some(_)) => {}
+                ("gid", None) => {
+                    let gid =
+                        map_gid(value, &template.tile_set, &self.tile_sets)
+                            .ok_or_else(|| Error::Schema {
+                                file: Some(file.to_path_buf()),
+                                json_path: json_path.clone(),
+                                message:
+                                    "the template's tileset is not one of \
+                                      the map's tilesets"
+                                        .into(),
+                            })?;
+                    inherited.insert(key.into(), gid.clone());
+                    instance.insert(key.into(), gid);
+                }
+                (_, None) => {
+                    inherited.insert(key.into(), value.clone());
+                    instance.insert(key.into(), value.clone());
+                }
+            }
+        }
+        instance.insert(INHERITED.into(), Value::Object(inherited));
+        Ok(())
+    }
+
+    /// Loads a template in to `loaded` if it isn't already. `file`
+    /// references it.
+    fn load<'b>(
+        loaded: &'b mut HashMap<PathBuf, Template>,
+        loader: &dyn ResourceLoader,
+        file: &Path,
+        path: PathBuf,
+        json_path: String,
+    ) -> Result<&'b Template, Error> {
+        if !loaded.contains_key(&path) {
+            let bytes = read_reference(loader, &path, Some(file), json_path)?;
+            let mut value = read_value(&bytes, Some(&path))?;
+            let object = match value.get_mut("object") {
+                Some(object) => object.take(),
+                None => {
+                    return Err(Error::Schema {
+                        file: Some(path),
+                        json_path: String::new(),
+                        message: "missing field `object`".into(),
+                    })
+                }
+            };
+            let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+            let tile_set = match value.get("tileset") {
+                Some(tile_set) => {
+                    let json_path = "tileset.firstgid".to_string();
+                    let first_gid = first_gid(tile_set, &path, json_path)?;
+                    let source = tile_set.get("source").and_then(Value::as_str);
+                    first_gid.zip(source).map(|(first_gid, source)| {
+                        (first_gid, normalise(&base_dir.join(source)))
+                    })
+                }
+                None => None,
+            };
+            loaded.insert(path.clone(), Template { object, tile_set });
+        }
+        Ok(&loaded[&path])
+    }
+}
+
+/// Adds the template's properties which `properties` doesn't have a value
+/// for, returning those added. A malformed property is left for the
+/// deserializer to report.
+fn merge_properties(
+    properties: &mut Vec<Value>,
+    template: &Value,
+) -> Vec<Value> {
+    let template = match template {
+        Value::Array(template) => template,
+        _ => return Vec::new(),
+    };
+    let mut added = Vec::new();
+    for property in template {
+        let name = property.get("name");
+        if !properties.iter().any(|p| p.get("name") == name) {
+            properties.push(property.clone());
+            added.push(property.clone());
+        }
+    }
+    added
+}
+
+/// Translates a template's `gid`, which is relative to the template's own
+/// tileset, to the GID of the same tile in the map. Its flags are kept.
+fn map_gid(
+    gid: &Value,
+    template: &Option<(u32, PathBuf)>,
+    map: &[(u32, PathBuf)],
+) -> Option<Value> {
+    // Anything other than a GID is left for the deserializer to report
+    let gid = match gid.as_u64() {
+        Some(gid) if gid <= u32::MAX as u64 => Gid(gid as u32),
+        _ => return Some(gid.clone()),
+    };
+    if gid.is_empty() {
+        return Some(gid.raw().into());
+    }
+    let (template_first_gid, source) = template.as_ref()?;
+    let (first_gid, _) = map.iter().find(|(_, path)| path == source)?;
+    let id = gid
+        .id()
+        .checked_sub(*template_first_gid)?
+        .checked_add(*first_gid)
+        .filter(|id| id & Gid::FLAGS == 0)?;
+    Some((id | (gid.raw() & Gid::FLAGS)).into())
+}
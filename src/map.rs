@@ -12,28 +12,90 @@
 //! to more usable data, eg: HashMaps, if desired. In all
 //! other cases non-copy types are passed by value.
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fmt;
+use std::sync::OnceLock;
 
 use crate::{
     layer::Layer,
-    parsers::{parse_color, parse_property},
+    parsers::{
+        default_compression_level, parse_color, parse_property, parse_version,
+    },
     tile_set::TileSet,
+    tree::LayerIndex,
+    writers::{is_default, is_default_compression_level, write_property},
     Color, TiledValue,
 };
 
 /// The base structure which contains all data - as in the root of a tree
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
 pub struct Map {
+    /// The version of the file format, eg; `1.10`. See `format_version`.
     #[serde(
-        rename(deserialize = "backgroundcolor"),
+        deserialize_with = "parse_version",
+        default,
+        skip_serializing_if = "String::is_empty"
+    )]
+    pub version: String,
+    /// The version of Tiled which saved the map, eg; `1.10.2`. See
+    /// `editor_version`.
+    #[serde(rename = "tiledversion", skip_serializing_if = "Option::is_none")]
+    pub tiled_version: Option<String>,
+    /// The id the next layer added will have. See `new_layer_id`.
+    #[serde(
+        rename = "nextlayerid",
+        default,
+        skip_serializing_if = "is_default"
+    )]
+    pub next_layer_id: u32,
+    /// The id the next object added will have. See `new_object_id`.
+    #[serde(
+        rename = "nextobjectid",
+        default,
+        skip_serializing_if = "is_default"
+    )]
+    pub next_object_id: u32,
+    /// The level tile data is compressed at by `Map::to_string` and
+    /// `Map::save_to_file`, where -1 leaves it to the codec (default: -1)
+    #[serde(
+        rename = "compressionlevel",
+        default = "default_compression_level",
+        skip_serializing_if = "is_default_compression_level"
+    )]
+    pub compression_level: i32,
+    /// The class of the map, as defined in Tiled's custom types
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub class: String,
+    /// Horizontal point in pixels the parallax of layers is relative to
+    #[serde(
+        rename = "parallaxoriginx",
+        default,
+        skip_serializing_if = "is_default"
+    )]
+    pub parallax_origin_x: f32,
+    /// Vertical point in pixels the parallax of layers is relative to
+    #[serde(
+        rename = "parallaxoriginy",
+        default,
+        skip_serializing_if = "is_default"
+    )]
+    pub parallax_origin_y: f32,
+    #[serde(
+        rename = "editorsettings",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub editor_settings: Option<EditorSettings>,
+    #[serde(
+        rename = "backgroundcolor",
         deserialize_with = "parse_color",
-        default
+        default,
+        skip_serializing_if = "is_default"
     )]
     /// The background colour is translated from the hex representation
     pub background_color: Color,
     /// Length of the side of a hex tile in pixels
-    #[serde(rename(deserialize = "hexsidelength"))]
+    #[serde(rename = "hexsidelength", skip_serializing_if = "Option::is_none")]
     pub hex_side_length: Option<u32>,
     /// Whether the map has infinite dimensions
     #[serde(default)]
@@ -45,62 +107,195 @@ pub struct Map {
     /// `Orthogonal`, `Isometric`, `Staggered` or `Hexagonal`
     pub orientation: Orientation,
     /// Rendering direction (orthogonal maps only)
-    #[serde(rename(deserialize = "renderorder"))]
+    #[serde(rename = "renderorder", skip_serializing_if = "Option::is_none")]
     pub render_order: Option<RenderOrder>,
     /// `X` or `Y` (staggered / hexagonal maps only)
-    #[serde(rename(deserialize = "staggeraxis"))]
+    #[serde(rename = "staggeraxis", skip_serializing_if = "Option::is_none")]
     pub stagger_axis: Option<StaggerAxis>,
     /// `Odd` or `Even` (staggered / hexagonal maps only)
-    #[serde(rename(deserialize = "staggerindex"))]
+    #[serde(rename = "staggerindex", skip_serializing_if = "Option::is_none")]
     pub stagger_index: Option<StaggerIndex>,
     /// Height in pixels for tiles in this map
-    #[serde(rename(deserialize = "tileheight"))]
+    #[serde(rename = "tileheight")]
     pub tile_height: u32,
     /// Width in pixels for tiles in this map
-    #[serde(rename(deserialize = "tilewidth"))]
+    #[serde(rename = "tilewidth")]
     pub tile_width: u32,
     /// The number of tile rows for the map
     pub height: u32,
     /// The number of tile columns for the map
     pub width: u32,
-    #[serde(rename(deserialize = "tilesets"))]
+    #[serde(rename = "tilesets")]
     pub tile_sets: Vec<TileSet>,
-    #[serde(deserialize_with = "parse_property", default)]
+    #[serde(
+        deserialize_with = "parse_property",
+        serialize_with = "write_property",
+        default,
+        skip_serializing_if = "HashMap::is_empty"
+    )]
     pub properties: HashMap<String, TiledValue>,
+    #[serde(skip)]
+    pub(crate) tile_set_index: TileSetIndex,
+    #[serde(skip)]
+    pub(crate) layer_index: LayerIndex,
+}
+
+/// The tilesets of a map ordered by first GID, so the one a GID is in can
+/// be found by binary search. It's built on first use, and as a cache it
+/// never makes two maps unequal.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct TileSetIndex(OnceLock<Vec<(u32, usize)>>);
+
+impl TileSetIndex {
+    /// The index in to `tile_sets` of the tileset which would hold `id`,
+    /// if it has that many tiles. `None` if the index is found to be out of
+    /// date with `tile_sets`.
+    pub(crate) fn find(
+        &self,
+        tile_sets: &[TileSet],
+        id: u32,
+    ) -> Option<Option<usize>> {
+        let index = self.0.get_or_init(|| {
+            let mut index: Vec<(u32, usize)> = tile_sets
+                .iter()
+                .enumerate()
+                .map(|(i, tile_set)| (tile_set.first_gid(), i))
+                .collect();
+            index.sort();
+            index
+        });
+        if index.len() != tile_sets.len() {
+            return None;
+        }
+        let after = index.partition_point(|&(first_gid, _)| first_gid <= id);
+        match after.checked_sub(1).map(|i| index[i]) {
+            Some((first_gid, i)) if tile_sets[i].first_gid() != first_gid => {
+                None
+            }
+            found => Some(found.map(|(_, i)| i)),
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.0 = OnceLock::new();
+    }
+}
+
+impl PartialEq for TileSetIndex {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+/// A version of Tiled or its file format, which orders as versions do, so
+/// features can be checked for with eg;
+/// `map.format_version() >= Some(Version::new(1, 9, 0))`
+#[derive(Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct Version {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl Version {
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Version {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// Reads `major.minor.patch`, where the minor and patch numbers may be
+    /// left out as 0. Anything after a `-`, such as `-beta`, is ignored.
+    pub fn parse(version: &str) -> Option<Self> {
+        let release = version.trim().split('-').next()?;
+        let mut numbers = release.split('.').map(str::parse::<u32>);
+        let major = numbers.next()?.ok()?;
+        let minor = numbers.next().unwrap_or(Ok(0)).ok()?;
+        let patch = numbers.next().unwrap_or(Ok(0)).ok()?;
+        if numbers.next().is_some() {
+            return None;
+        }
+        Some(Version::new(major, minor, patch))
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// Settings only used by Tiled while editing the map
+#[derive(Deserialize, Serialize, Debug, Default, PartialEq, Clone)]
+pub struct EditorSettings {
+    /// The size of the chunks new tiles go in on infinite maps
+    #[serde(rename = "chunksize", skip_serializing_if = "Option::is_none")]
+    pub chunk_size: Option<ChunkSize>,
+    /// Where the map was last exported to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub export: Option<ExportSettings>,
+}
+
+/// In tiles (default: 16 by 16)
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, Copy)]
+pub struct ChunkSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Default for ChunkSize {
+    fn default() -> Self {
+        ChunkSize {
+            width: 16,
+            height: 16,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Default, PartialEq, Clone)]
+pub struct ExportSettings {
+    /// The file last exported to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    /// The format last exported as, eg; `json`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
 }
 
 /// Rendering direction. Applies only to orthogonal maps
-#[derive(Deserialize, Debug, PartialEq, Clone)]
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
 pub enum RenderOrder {
-    #[serde(rename(deserialize = "right-down"))]
+    #[serde(rename = "right-down")]
     RightDown,
-    #[serde(rename(deserialize = "right-up"))]
+    #[serde(rename = "right-up")]
     RightUp,
-    #[serde(rename(deserialize = "left-down"))]
+    #[serde(rename = "left-down")]
     LeftDown,
-    #[serde(rename(deserialize = "left-up"))]
+    #[serde(rename = "left-up")]
     LeftUp,
 }
 
 /// Applies only to staggered or hexagonal maps
-#[derive(Deserialize, Debug, PartialEq, Clone)]
-#[serde(rename_all(deserialize = "lowercase"))]
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+#[serde(rename_all = "lowercase")]
 pub enum StaggerAxis {
     X,
     Y,
 }
 
 /// Applies only to staggered or hexagonal maps
-#[derive(Deserialize, Debug, PartialEq, Clone)]
-#[serde(rename_all(deserialize = "lowercase"))]
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+#[serde(rename_all = "lowercase")]
 pub enum StaggerIndex {
     Odd,
     Even,
 }
 
 /// The orientation of the `Map`
-#[derive(Deserialize, Debug, PartialEq, Eq, Clone)]
-#[serde(rename_all(deserialize = "lowercase"))]
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone)]
+#[serde(rename_all = "lowercase")]
 pub enum Orientation {
     Orthogonal,
     Isometric,
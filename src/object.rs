@@ -4,22 +4,34 @@
 
 use crate::{
     parsers::{parse_color, parse_property},
-    Color, TiledValue, Vec2,
+    writers::{is_default, write_property},
+    Color, Gid, TiledValue, Vec2,
 };
 use serde::de::Error;
-use serde::{Deserialize, Deserializer};
+use serde::ser::{Error as _, SerializeMap};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{Map as JsonMap, Value};
+use std::borrow::Cow;
 use std::collections::HashMap;
-use std::fs::File;
-use std::path::PathBuf;
+
+/// Where the loader keeps the fields a template filled in on an instance.
+/// Must match the name of `inherited` in `Object`'s helper.
+pub(crate) const INHERITED: &str = "tiled-json-rs:inherited";
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Object {
-    // GID, only if object comes from a Tilemap
-    pub gid: Option<u32>,
+    /// GID with flip flags, only if object comes from a Tilemap. A tile
+    /// object also has it as `ObjectType::Tile`, which is the one saved.
+    pub gid: Option<Gid>,
     /// Incremental id - unique across all objects
     pub id: Option<u32>,
     pub name: String,
     pub custom_type: String,
+    /// The class of the object, as defined in Tiled's custom types. Tiled
+    /// 1.9 wrote the type of an object here rather than in `type`.
+    pub class: String,
+    /// Whether the object is shown (default: true)
+    pub visible: bool,
     /// Angle in degrees clockwise
     pub rotation: f32,
     pub height: f32,
@@ -29,17 +41,81 @@ pub struct Object {
     /// Y coordinate in pixels
     pub y: f32,
     pub properties: HashMap<String, TiledValue>,
-    /// An *almost* concrete type. Some types aren't included in this, eg; a square
-    /// which can be derived from the X/Y & Height/Width
-    ///
-    /// Types can be:
-    /// - Point
-    /// - Polygon
-    /// - Polyline
-    /// - Text
-    /// - Ellipse
-    /// - or None
+    /// The shape of the object, which is a `Rectangle` of its width and
+    /// height unless the object says otherwise
     pub object_type: ObjectType,
+    /// The template this object is an instance of, once it has been filled
+    /// in from it. An unresolved instance is an `ObjectType::Template`.
+    pub template: Option<TemplateRef>,
+}
+
+/// The template of a resolved instance. The object is saved with only the
+/// fields which differ from the template's, plus the path, so that Tiled
+/// keeps it linked to the template.
+#[derive(Debug, Clone)]
+pub struct TemplateRef {
+    /// The path to the template, relative to the file of the object
+    pub path: String,
+    /// The fields the template gave the object when it was loaded
+    inherited: JsonMap<String, Value>,
+}
+
+impl TemplateRef {
+    /// A reference to the template at `path`. The object is saved with all
+    /// of its fields, as none of them are known to be the template's.
+    pub fn new(path: impl Into<String>) -> Self {
+        TemplateRef {
+            path: path.into(),
+            inherited: JsonMap::new(),
+        }
+    }
+
+    /// Removes the fields of `object` which are the same as the template's.
+    /// A tile's GID is kept, as it's the map's, and the template's tileset
+    /// may no longer be one of the map's once it has been saved.
+    fn remove_inherited(&self, object: &mut JsonMap<String, Value>) {
+        for (key, value) in &self.inherited {
+            if key != "properties"
+                && key != "gid"
+                && object.get(key).is_some_and(|v| same(v, value))
+            {
+                object.remove(key);
+            }
+        }
+    }
+
+    /// The properties which are the same as the template's
+    fn inherited_properties(&self) -> HashMap<String, TiledValue> {
+        self.inherited
+            .get("properties")
+            .and_then(|properties| parse_property(properties.clone()).ok())
+            .unwrap_or_default()
+    }
+}
+
+/// Two templates are the same if they're at the same path
+impl PartialEq for TemplateRef {
+    fn eq(&self, other: &Self) -> bool {
+        self.path == other.path
+    }
+}
+
+/// Whether a written value is the same as one read from a template, where
+/// numbers are compared at the precision they're kept at
+fn same(written: &Value, template: &Value) -> bool {
+    match (written, template) {
+        (Value::Number(a), Value::Number(b)) => {
+            a.as_f64().map(|a| a as f32) == b.as_f64().map(|b| b as f32)
+        }
+        (Value::Array(a), Value::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| same(a, b))
+        }
+        (Value::Object(a), Value::Object(b)) => {
+            a.len() == b.len()
+                && a.iter().all(|(k, a)| b.get(k).is_some_and(|b| same(a, b)))
+        }
+        _ => written == template,
+    }
 }
 
 impl<'de> Deserialize<'de> for Object {
@@ -47,143 +123,357 @@ impl<'de> Deserialize<'de> for Object {
     where
         D: Deserializer<'de>,
     {
-        #[derive(Deserialize, Debug)]
-        #[serde(rename_all = "lowercase")]
-        struct ExternalBase {
-            id: u32,
-            template: String,
-            #[serde(deserialize_with = "parse_property", default)]
-            properties: HashMap<String, TiledValue>,
+        #[derive(Deserialize)]
+        struct Helper {
+            gid: Option<Gid>,
+            id: Option<u32>,
+            name: Option<String>,
+            #[serde(rename = "type")]
+            custom_type: Option<String>,
+            class: Option<String>,
+            visible: Option<bool>,
+            rotation: Option<f32>,
+            height: Option<f32>,
+            width: Option<f32>,
             #[serde(default)]
             x: f32,
             #[serde(default)]
             y: f32,
+            #[serde(deserialize_with = "parse_property", default)]
+            properties: HashMap<String, TiledValue>,
+            template: Option<String>,
+            ellipse: Option<bool>,
+            point: Option<bool>,
+            polygon: Option<Vec<Vec2<f32>>>,
+            polyline: Option<Vec<Vec2<f32>>>,
+            text: Option<Text>,
+            #[serde(rename = "tiled-json-rs:inherited")]
+            inherited: Option<JsonMap<String, Value>>,
         }
 
-        #[derive(Deserialize, Debug)]
-        struct Intermediate {
-            object: ExternalDetails,
-        }
+        let helper = Helper::deserialize(deserializer)?;
+        let shape = Shape {
+            gid: helper.gid,
+            ellipse: helper.ellipse,
+            point: helper.point,
+            polygon: helper.polygon,
+            polyline: helper.polyline,
+            text: helper.text,
+        };
 
-        #[derive(Deserialize, Debug)]
-        #[serde(rename_all = "lowercase")]
-        struct ExternalDetails {
-            gid: Option<u32>,
-            _id: Option<u32>,
-            name: String,
-            #[serde(rename(deserialize = "type"), default)]
-            custom_type: String,
-            rotation: f32,
-            height: f32,
-            width: f32,
-            #[serde(flatten)]
-            object_type: ObjectType,
+        // The loader fills in template instances from their template. One
+        // it couldn't, such as in a map loaded from a string, only has the
+        // fields it changes.
+        if let Some(template) = &helper.template {
+            if helper.name.is_none()
+                || helper.rotation.is_none()
+                || helper.height.is_none()
+                || helper.width.is_none()
+            {
+                return Ok(Object {
+                    gid: helper.gid,
+                    id: helper.id,
+                    name: helper.name.unwrap_or_default(),
+                    custom_type: helper.custom_type.unwrap_or_default(),
+                    class: helper.class.unwrap_or_default(),
+                    visible: helper.visible.unwrap_or(true),
+                    rotation: helper.rotation.unwrap_or_default(),
+                    height: helper.height.unwrap_or_default(),
+                    width: helper.width.unwrap_or_default(),
+                    x: helper.x,
+                    y: helper.y,
+                    properties: helper.properties,
+                    object_type: ObjectType::Template(template.clone()),
+                    template: None,
+                });
+            }
         }
 
-        #[derive(Deserialize)]
-        struct Internal {
-            gid: Option<u32>,
+        let inherited = helper.inherited.unwrap_or_default();
+        Ok(Object {
+            gid: helper.gid,
+            id: helper.id,
+            name: helper.name.ok_or_else(|| Error::missing_field("name"))?,
+            custom_type: helper.custom_type.unwrap_or_default(),
+            class: helper.class.unwrap_or_default(),
+            visible: helper.visible.unwrap_or(true),
+            rotation: helper
+                .rotation
+                .ok_or_else(|| Error::missing_field("rotation"))?,
+            height: helper
+                .height
+                .ok_or_else(|| Error::missing_field("height"))?,
+            width: helper.width.ok_or_else(|| Error::missing_field("width"))?,
+            x: helper.x,
+            y: helper.y,
+            properties: helper.properties,
+            object_type: shape.object_type()?,
+            template: helper
+                .template
+                .map(|path| TemplateRef { path, inherited }),
+        })
+    }
+}
+
+impl Serialize for Object {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        #[derive(Serialize)]
+        struct Helper<'a> {
+            #[serde(skip_serializing_if = "Option::is_none")]
+            gid: Option<Gid>,
+            #[serde(skip_serializing_if = "Option::is_none")]
             id: Option<u32>,
-            name: String,
-            #[serde(rename(deserialize = "type"), default)]
-            custom_type: String,
-            rotation: f32,
-            height: f32,
-            width: f32,
-            #[serde(default)]
+            #[serde(skip_serializing_if = "Option::is_none")]
+            name: Option<&'a str>,
+            #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+            custom_type: Option<&'a str>,
+            #[serde(skip_serializing_if = "str::is_empty")]
+            class: &'a str,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            visible: Option<bool>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            rotation: Option<f32>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            height: Option<f32>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            width: Option<f32>,
             x: f32,
-            #[serde(default)]
             y: f32,
-            #[serde(deserialize_with = "parse_property", default)]
-            properties: HashMap<String, TiledValue>,
+            #[serde(
+                serialize_with = "write_property",
+                skip_serializing_if = "HashMap::is_empty"
+            )]
+            properties: Cow<'a, HashMap<String, TiledValue>>,
             #[serde(flatten)]
-            object_type: ObjectType,
+            object_type: &'a ObjectType,
         }
 
-        #[derive(Deserialize)]
-        #[serde(untagged)]
-        enum Helper {
-            Internal(Internal),
-            External(ExternalBase),
+        // An unresolved template instance is written with only the fields
+        // it changes, so the template still provides the rest
+        let unresolved = matches!(self.object_type, ObjectType::Template(_));
+        fn field<T: Default + PartialEq>(
+            value: T,
+            unresolved: bool,
+        ) -> Option<T> {
+            if unresolved && is_default(&value) {
+                None
+            } else {
+                Some(value)
+            }
         }
 
-        let v = serde_json::Value::deserialize(deserializer)?;
-        match Helper::deserialize(&v) {
-            Ok(m) => {
-                match m {
-                    Helper::Internal(t) => {
-                        return Ok(Object {
-                            gid: t.gid,
-                            id: t.id,
-                            name: t.name,
-                            custom_type: t.custom_type,
-                            rotation: t.rotation,
-                            height: t.height,
-                            width: t.width,
-                            x: t.x,
-                            y: t.y,
-                            properties: t.properties,
-                            object_type: t.object_type,
-                        });
-                    }
-                    Helper::External(object_base) => {
-                        let path = PathBuf::from(&object_base.template);
-                        let file = File::open(path)
-                            .map_err(|e| Error::custom(format!("{:?}", e)))?;
-                        let intermediate: Intermediate =
-                            serde_json::from_reader(file).map_err(|e| {
-                                Error::custom(format!("{:?}", e))
-                            })?;
-
-                        return Ok(Object {
-                            gid: intermediate.object.gid,
-                            id: Some(object_base.id),
-                            name: intermediate.object.name,
-                            custom_type: intermediate.object.custom_type,
-                            rotation: intermediate.object.rotation,
-                            height: intermediate.object.height,
-                            width: intermediate.object.width,
-                            x: object_base.x,
-                            y: object_base.y,
-                            properties: object_base.properties,
-                            object_type: intermediate.object.object_type,
-                        });
-                    }
+        // A resolved instance leaves what it shares with its template out
+        let properties = match &self.template {
+            Some(template) => {
+                let inherited = template.inherited_properties();
+                Cow::Owned(
+                    self.properties
+                        .iter()
+                        .filter(|&(name, value)| {
+                            inherited.get(name) != Some(value)
+                        })
+                        .map(|(name, value)| (name.clone(), value.clone()))
+                        .collect(),
+                )
+            }
+            None => Cow::Borrowed(&self.properties),
+        };
+
+        let helper = Helper {
+            // Written by `ObjectType::Tile` instead
+            gid: match self.object_type {
+                ObjectType::Tile(_) => None,
+                _ => self.gid,
+            },
+            id: self.id,
+            name: field(self.name.as_str(), unresolved),
+            custom_type: field(self.custom_type.as_str(), unresolved),
+            class: &self.class,
+            visible: Some(self.visible).filter(|&v| !(unresolved && v)),
+            rotation: field(self.rotation, unresolved),
+            height: field(self.height, unresolved),
+            width: field(self.width, unresolved),
+            x: self.x,
+            y: self.y,
+            properties,
+            object_type: &self.object_type,
+        };
+        match &self.template {
+            Some(template) => {
+                let mut object = match serde_json::to_value(helper) {
+                    Ok(Value::Object(object)) => object,
+                    Ok(_) => unreachable!("an object is written as a map"),
+                    Err(err) => return Err(S::Error::custom(err)),
                 };
+                template.remove_inherited(&mut object);
+                object.insert("template".into(), template.path.clone().into());
+                object.serialize(serializer)
             }
-            Err(e) => return Err(Error::custom(format!("{:?}", e))),
+            None => helper.serialize(serializer),
         }
     }
 }
 
-#[derive(Deserialize, Debug, PartialEq, Clone)]
+/// The text and style of a text object. Fields which Tiled leaves out take
+/// its defaults.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
 pub struct Text {
-    /// 0000: off, 0001: bold, 0010:italic, 0100: wrap (1, 2, 4, 8)
-    pub flags: u8,
-    #[serde(deserialize_with = "parse_color", default)]
-    pub color: Color,
     pub text: String,
+    /// (default: sans-serif)
+    #[serde(
+        rename = "fontfamily",
+        default = "sans_serif",
+        skip_serializing_if = "is_sans_serif"
+    )]
+    pub font_family: String,
+    /// Font size in pixels (default: 16)
+    #[serde(
+        rename = "pixelsize",
+        default = "sixteen",
+        skip_serializing_if = "is_sixteen"
+    )]
+    pub pixel_size: u32,
+    /// Whether the text wraps within the object's bounds (default: false)
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub wrap: bool,
+    /// (default: opaque black)
+    #[serde(
+        deserialize_with = "parse_color",
+        default = "black",
+        skip_serializing_if = "is_black"
+    )]
+    pub color: Color,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub bold: bool,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub italic: bool,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub underline: bool,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub strikeout: bool,
+    /// Whether kerning is used when placing characters (default: true)
+    #[serde(default = "yes", skip_serializing_if = "is_yes")]
+    pub kerning: bool,
+    #[serde(rename = "halign", default, skip_serializing_if = "is_default")]
+    pub horizontal_align: HorizontalAlign,
+    #[serde(rename = "valign", default, skip_serializing_if = "is_default")]
+    pub vertical_align: VerticalAlign,
+}
+
+/// How text is aligned within the width of its object
+#[derive(Deserialize, Serialize, Debug, Default, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum HorizontalAlign {
+    #[default]
+    Left,
+    Center,
+    Right,
+    /// Stretched to fill each line, bar the last
+    Justify,
+}
+
+/// How text is aligned within the height of its object
+#[derive(Deserialize, Serialize, Debug, Default, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum VerticalAlign {
+    #[default]
+    Top,
+    Center,
+    Bottom,
+}
+
+// The defaults of `Text`, which are particular to it
+fn sans_serif() -> String {
+    String::from("sans-serif")
+}
+
+fn is_sans_serif(family: &String) -> bool {
+    family == "sans-serif"
+}
+
+fn sixteen() -> u32 {
+    16
+}
+
+fn is_sixteen(size: &u32) -> bool {
+    *size == 16
+}
+
+fn black() -> Color {
+    Color(0, 0, 0, 255)
+}
+
+fn is_black(color: &Color) -> bool {
+    *color == black()
+}
+
+fn yes() -> bool {
+    true
+}
+
+fn is_yes(value: &bool) -> bool {
+    *value
 }
 
 /// Contains data for the object sub-types
 #[derive(Debug, PartialEq, Clone)]
 pub enum ObjectType {
+    /// The object's width and height, from its position
+    Rectangle,
+    /// Fills the object's width and height
     Ellipse,
     Point,
-    Polygon(Vec<Vec2<i32>>),
-    PolyLine(Vec<Vec2<i32>>),
+    /// Points in pixels relative to the object's position. Tiled keeps
+    /// fractions of a pixel; `Vec2::round` gives whole pixels.
+    Polygon(Vec<Vec2<f32>>),
+    PolyLine(Vec<Vec2<f32>>),
     Text(Text),
+    /// A tile drawn at the object's width and height, with its flip flags
+    Tile(Gid),
+    /// An instance of a template which wasn't loaded, with the path to the
+    /// template. The object's other fields are only those it overrides.
     Template(String),
-    None,
 }
 
 impl ObjectType {
-    pub fn is_none(&self) -> bool {
-        *self == ObjectType::None
+    /// The points of a polygon or polyline
+    pub fn points(&self) -> Option<&[Vec2<f32>]> {
+        match self {
+            ObjectType::Polygon(points) | ObjectType::PolyLine(points) => {
+                Some(points)
+            }
+            _ => None,
+        }
     }
+}
 
-    pub fn is_some(&self) -> bool {
-        *self != ObjectType::None
+/// Written as the single field of an object that gives its shape, if any
+impl Serialize for ObjectType {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(None)?;
+        match self {
+            ObjectType::Ellipse => map.serialize_entry("ellipse", &true)?,
+            ObjectType::Point => map.serialize_entry("point", &true)?,
+            ObjectType::Polygon(points) => {
+                map.serialize_entry("polygon", points)?
+            }
+            ObjectType::PolyLine(points) => {
+                map.serialize_entry("polyline", points)?
+            }
+            ObjectType::Text(text) => map.serialize_entry("text", text)?,
+            ObjectType::Tile(gid) => map.serialize_entry("gid", gid)?,
+            ObjectType::Template(path) => {
+                map.serialize_entry("template", path)?
+            }
+            ObjectType::Rectangle => {}
+        }
+        map.end()
     }
 }
 
@@ -192,63 +482,51 @@ impl<'de> Deserialize<'de> for ObjectType {
     where
         D: Deserializer<'de>,
     {
-        #[derive(Deserialize)]
-        #[serde(rename_all = "lowercase")]
-        enum Helper {
-            Ellipse(bool),
-            Point(bool),
-            Polygon(Vec<Vec2<i32>>),
-            Polyline(Vec<Vec2<i32>>),
-            Text {
-                bold: bool,
-                #[serde(deserialize_with = "parse_color", default)]
-                color: Color,
-                italic: bool,
-                text: String,
-                wrap: bool,
-            },
-        }
+        Shape::deserialize(deserializer)?.object_type()
+    }
+}
+
+/// The optional fields of an object which together decide its `ObjectType`.
+/// Only one of them may be given, other than `gid`.
+#[derive(Deserialize)]
+struct Shape {
+    gid: Option<Gid>,
+    ellipse: Option<bool>,
+    point: Option<bool>,
+    polygon: Option<Vec<Vec2<f32>>>,
+    polyline: Option<Vec<Vec2<f32>>>,
+    text: Option<Text>,
+}
 
-        let v = serde_json::Value::deserialize(deserializer)?;
-        if let Ok(m) = Helper::deserialize(&v) {
-            return match m {
-                Helper::Ellipse(b) => {
-                    if b {
-                        Ok(ObjectType::Ellipse)
-                    } else {
-                        Ok(ObjectType::None)
-                    }
-                }
-                Helper::Point(b) => {
-                    if b {
-                        Ok(ObjectType::Point)
-                    } else {
-                        Ok(ObjectType::None)
-                    }
-                }
-                Helper::Polygon(data) => Ok(ObjectType::Polygon(data)),
-                Helper::Polyline(data) => Ok(ObjectType::PolyLine(data)),
-                Helper::Text {
-                    bold,
-                    color,
-                    italic,
-                    text,
-                    wrap,
-                } => {
-                    let mut flags = 0u8;
-                    if bold {
-                        flags |= 1
-                    }
-                    if italic {
-                        flags |= 2
-                    }
-                    if wrap {
-                        flags |= 4
-                    }
-                    Ok(ObjectType::Text(Text { flags, color, text }))
-                }
-            };
+impl Shape {
+    fn object_type<E: Error>(self) -> Result<ObjectType, E> {
+        let shapes = [
+            self.ellipse == Some(true),
+            self.point == Some(true),
+            self.polygon.is_some(),
+            self.polyline.is_some(),
+            self.text.is_some(),
+        ];
+        if shapes.iter().filter(|&&shape| shape).count() > 1 {
+            return Err(E::custom(
+                "an object can only be one of an ellipse, point, polygon, \
+                 polyline or text",
+            ));
         }
-        Ok(ObjectType::None)
+        Ok(if self.ellipse == Some(true) {
+            ObjectType::Ellipse
+        } else if self.point == Some(true) {
+            ObjectType::Point
+        } else if let Some(points) = self.polygon {
+            ObjectType::Polygon(points)
+        } else if let Some(points) = self.polyline {
+            ObjectType::PolyLine(points)
+        } else if let Some(text) = self.text {
+            ObjectType::Text(text)
+        } else if let Some(gid) = self.gid {
+            ObjectType::Tile(gid)
+        } else {
+            ObjectType::Rectangle
+        })
     }
 }
@@ -2,14 +2,71 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use crate::{Color, TiledValue};
-use base64;
+use crate::{Color, Compression, Gid, TiledValue};
 use serde::de::{self, SeqAccess, Visitor};
 use serde::{Deserialize, Deserializer};
 use std::collections::HashMap;
 use std::fmt;
 use std::path::{Path, PathBuf};
 
+/// The default of fields, such as parallax factors, which are 1 unless
+/// set otherwise
+pub fn one() -> f32 {
+    1.0
+}
+
+/// The default of `compressionlevel`, which leaves the level to the codec
+pub fn default_compression_level() -> i32 {
+    -1
+}
+
+/// A version string, which Tiled before 1.2 wrote as a number
+pub fn parse_version<'de, D>(de: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct SomeVisitor;
+
+    impl<'de> Visitor<'de> for SomeVisitor {
+        type Value = String;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a version as a string or number")
+        }
+
+        fn visit_str<E>(self, value: &str) -> Result<String, E>
+        where
+            E: de::Error,
+        {
+            Ok(value.to_string())
+        }
+
+        fn visit_u64<E>(self, value: u64) -> Result<String, E>
+        where
+            E: de::Error,
+        {
+            Ok(value.to_string())
+        }
+
+        fn visit_i64<E>(self, value: i64) -> Result<String, E>
+        where
+            E: de::Error,
+        {
+            Ok(value.to_string())
+        }
+
+        fn visit_f64<E>(self, value: f64) -> Result<String, E>
+        where
+            E: de::Error,
+        {
+            Ok(value.to_string())
+        }
+    }
+
+    let visitor = SomeVisitor;
+    de.deserialize_any(visitor)
+}
+
 pub fn parse_tileset_tiles<'de, D>(
     de: D,
 ) -> Result<HashMap<u32, crate::Tile>, D::Error>
@@ -32,7 +89,9 @@ where
         where
             S: SeqAccess<'de>,
         {
-            let mut tiles = HashMap::<u32, Tile>::with_capacity(seq.size_hint().unwrap_or(0));
+            let mut tiles = HashMap::<u32, Tile>::with_capacity(
+                seq.size_hint().unwrap_or(0),
+            );
             while let Some(tile) = seq.next_element::<Tile>()? {
                 tiles.insert(tile.id, tile);
             }
@@ -86,43 +145,34 @@ where
     de.deserialize_seq(visitor)
 }
 
-pub fn parse_data<'de, D>(de: D) -> Result<Vec<u32>, D::Error>
+pub fn parse_data<'de, D>(de: D) -> Result<Vec<Gid>, D::Error>
 where
     D: Deserializer<'de>,
 {
     struct SomeVisitor;
 
     impl<'de> Visitor<'de> for SomeVisitor {
-        type Value = Vec<u32>;
+        type Value = Vec<Gid>;
 
         fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
             formatter.write_str("enum of either array of u32 or string")
         }
 
-        fn visit_str<E>(self, value: &str) -> Result<Vec<u32>, E>
+        fn visit_str<E>(self, value: &str) -> Result<Vec<Gid>, E>
         where
             E: de::Error,
         {
-            // TODO: decompression
-            let decoded: Vec<u8> =
-                base64::decode(value).expect("Could not decode base64");
-            let mut corrected = Vec::with_capacity(decoded.len() / 4);
-            for chunk in decoded.chunks(4) {
-                let res = (chunk[0] as u32)
-                    + ((chunk[1] as u32) << 8)
-                    + ((chunk[2] as u32) << 16)
-                    + ((chunk[3] as u32) << 24);
-                corrected.push(res);
-            }
-            Ok(corrected)
+            // A lone chunk carries no compression field, so the data
+            // can only be plain base64 here
+            decode_data(value, None).map_err(E::custom)
         }
 
-        fn visit_seq<S>(self, mut seq: S) -> Result<Vec<u32>, S::Error>
+        fn visit_seq<S>(self, mut seq: S) -> Result<Vec<Gid>, S::Error>
         where
             S: SeqAccess<'de>,
         {
-            let mut v = Vec::<u32>::new();
-            while let Some(val) = seq.next_element::<u32>()? {
+            let mut v = Vec::<Gid>::new();
+            while let Some(val) = seq.next_element::<Gid>()? {
                 v.push(val);
             }
             Ok(v)
@@ -134,6 +184,131 @@ where
     de.deserialize_any(visitor)
 }
 
+/// The reason tile data could not be turned in to GIDs
+#[derive(Debug)]
+pub enum DataError {
+    Decode(String),
+    Decompress(String),
+}
+
+impl fmt::Display for DataError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DataError::Decode(e) => write!(f, "invalid tile data: {}", e),
+            DataError::Decompress(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// Decodes base64 tile data, inflating it first if the layer declared a
+/// `Compression`, and converts the little-endian bytes in to GIDs
+pub fn decode_data(
+    value: &str,
+    compression: Option<&Compression>,
+) -> Result<Vec<Gid>, DataError> {
+    let decoded: Vec<u8> =
+        base64::decode(value).map_err(|e| DataError::Decode(e.to_string()))?;
+    let decoded = match compression {
+        Some(compression) => {
+            decompress(&decoded, compression).map_err(DataError::Decompress)?
+        }
+        None => decoded,
+    };
+    if decoded.len() % 4 != 0 {
+        return Err(DataError::Decode(format!(
+            "{} bytes is not a whole number of 4 byte GIDs",
+            decoded.len()
+        )));
+    }
+    let mut corrected = Vec::with_capacity(decoded.len() / 4);
+    for chunk in decoded.chunks_exact(4) {
+        let res = (chunk[0] as u32)
+            + ((chunk[1] as u32) << 8)
+            + ((chunk[2] as u32) << 16)
+            + ((chunk[3] as u32) << 24);
+        corrected.push(Gid(res));
+    }
+    Ok(corrected)
+}
+
+#[cfg_attr(
+    not(any(feature = "zlib", feature = "gzip", feature = "zstd")),
+    allow(unused_variables)
+)]
+fn decompress(
+    data: &[u8],
+    compression: &Compression,
+) -> Result<Vec<u8>, String> {
+    match compression {
+        #[cfg(feature = "zlib")]
+        Compression::Zlib => {
+            use std::io::Read;
+            let mut out = Vec::with_capacity(data.len() * 4);
+            flate2::read::ZlibDecoder::new(data)
+                .read_to_end(&mut out)
+                .map_err(|e| format!("could not inflate zlib data: {}", e))?;
+            Ok(out)
+        }
+        #[cfg(feature = "gzip")]
+        Compression::Gzip => {
+            use std::io::Read;
+            let mut out = Vec::with_capacity(data.len() * 4);
+            flate2::read::GzDecoder::new(data)
+                .read_to_end(&mut out)
+                .map_err(|e| format!("could not inflate gzip data: {}", e))?;
+            Ok(out)
+        }
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => zstd::stream::decode_all(data)
+            .map_err(|e| format!("could not inflate zstd data: {}", e)),
+        #[allow(unreachable_patterns)]
+        other => Err(format!(
+            "{} compressed data requires the `{}` feature",
+            other.name(),
+            other.name()
+        )),
+    }
+}
+
+pub fn parse_compression<'de, D>(de: D) -> Result<Option<Compression>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct SomeVisitor;
+
+    impl<'de> Visitor<'de> for SomeVisitor {
+        type Value = Option<Compression>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("one of \"zlib\", \"gzip\", \"zstd\" or \"\"")
+        }
+
+        fn visit_str<E>(self, value: &str) -> Result<Option<Compression>, E>
+        where
+            E: de::Error,
+        {
+            match value {
+                "" => Ok(None),
+                "zlib" => Ok(Some(Compression::Zlib)),
+                "gzip" => Ok(Some(Compression::Gzip)),
+                "zstd" => Ok(Some(Compression::Zstd)),
+                _ => Err(E::unknown_variant(value, &["zlib", "gzip", "zstd"])),
+            }
+        }
+    }
+
+    let visitor = SomeVisitor;
+    de.deserialize_str(visitor)
+}
+
+/// For colours which are only sometimes given, such as a layer's tint
+pub fn parse_optional_color<'de, D>(de: D) -> Result<Option<Color>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    parse_color(de).map(Some)
+}
+
 pub fn parse_color<'de, D>(de: D) -> Result<Color, D::Error>
 where
     D: Deserializer<'de>,
@@ -144,29 +319,34 @@ where
         type Value = Color;
 
         fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-            formatter.write_str("color as a string of hex")
+            formatter
+                .write_str("color as a string of hex, #RRGGBB or #AARRGGBB")
         }
 
         fn visit_str<E>(self, value: &str) -> Result<Color, E>
         where
             E: de::Error,
         {
-            let value = if value.len() < 9 {
-                format!("#ff{}", &value[1..])
-            } else {
-                value.to_string()
+            let invalid =
+                || E::invalid_value(de::Unexpected::Str(value), &self);
+            let digits = match value.strip_prefix('#') {
+                // Checking for ASCII first keeps the slicing below on
+                // char boundaries
+                Some(digits) if digits.is_ascii() => digits,
+                _ => return Err(invalid()),
+            };
+            let digits = match digits.len() {
+                6 => format!("ff{}", digits),
+                8 => digits.to_string(),
+                _ => return Err(invalid()),
             };
             // Tiled puts alpha first
-            let alpha = &value[1..=2];
-            let red = &value[3..=4];
-            let green = &value[5..=6];
-            let blue = &value[7..=8];
-            Ok(Color(
-                parse_hex(red),
-                parse_hex(green),
-                parse_hex(blue),
-                parse_hex(alpha),
-            ))
+            let channel = |range| parse_hex(&digits[range]).ok_or_else(invalid);
+            let alpha = channel(0..2)?;
+            let red = channel(2..4)?;
+            let green = channel(4..6)?;
+            let blue = channel(6..8)?;
+            Ok(Color(red, green, blue, alpha))
         }
     }
 
@@ -174,14 +354,14 @@ where
     de.deserialize_str(visitor)
 }
 
-fn parse_hex(hex: &str) -> u32 {
+/// Reads hex digits, most significant first. Unlike `u32::from_str_radix`
+/// a sign isn't accepted.
+fn parse_hex(hex: &str) -> Option<u32> {
     let mut dec = 0;
-    let mut weight = 1;
     for c in hex.chars() {
-        dec += weight * c.to_digit(16).unwrap();
-        weight *= 16;
+        dec = dec * 16 + c.to_digit(16)?;
     }
-    dec
+    Some(dec)
 }
 
 pub fn parse_path<'de, D>(de: D) -> Result<PathBuf, D::Error>
@@ -216,6 +396,33 @@ mod tests {
     #[test]
     fn hex_check() {
         use crate::parsers::parse_hex;
-        assert_eq!(parse_hex("ffffff"), 16777215);
+        assert_eq!(parse_hex("ffffff"), Some(16777215));
+        assert_eq!(parse_hex("fg"), None);
+        assert_eq!(parse_hex("1a"), Some(26));
+    }
+
+    #[test]
+    fn color_check() {
+        use crate::parsers::parse_color;
+        use serde_json::{json, Value};
+
+        let parse = |v: Value| parse_color(v).ok();
+        assert!(parse(json!("#ffffff")).is_some());
+        assert!(parse(json!("#ffffffff")).is_some());
+        for bad in &["", "#", "ffffff", "#fff", "#fffffffff", "#gggggg"] {
+            assert_eq!(parse(json!(bad)), None, "{}", bad);
+        }
+        // Multi-byte characters must not be sliced through
+        assert_eq!(parse(json!("#ééé")), None);
+    }
+
+    #[test]
+    fn data_check() {
+        use crate::parsers::decode_data;
+
+        assert_eq!(decode_data("AQAAAAIAAAA=", None).unwrap(), vec![1, 2]);
+        // Six bytes is one and a half GIDs
+        assert!(decode_data("AQAAAAIA", None).is_err());
+        assert!(decode_data("!", None).is_err());
     }
 }
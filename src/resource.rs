@@ -0,0 +1,84 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::load::normalise;
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Where maps, and the tilesets and templates they reference, are read
+/// from.
+///
+/// Tiled stores a reference relative to the file it is in, so the path
+/// given to `read` is the reference joined to the directory of the file
+/// which made it, eg; `maps/../tilesets/terrain.json`. Implement this to
+/// load from an archive, or anywhere else that isn't the filesystem.
+///
+/// Any `Fn(&Path) -> io::Result<Vec<u8>>` is a `ResourceLoader`.
+pub trait ResourceLoader {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+}
+
+impl<F> ResourceLoader for F
+where
+    F: Fn(&Path) -> io::Result<Vec<u8>>,
+{
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        self(path)
+    }
+}
+
+/// Reads from the filesystem, relative to the working directory. This is
+/// what the loading functions without a loader use.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FileSystem;
+
+impl ResourceLoader for FileSystem {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        std::fs::read(path)
+    }
+}
+
+/// Files held in memory, for tests or assets embedded in the binary.
+///
+/// Paths are compared with `.` and `..` removed, so a file added as
+/// `tilesets/terrain.json` is found when a map in `maps` references
+/// `../tilesets/terrain.json`.
+#[derive(Debug, Default, Clone)]
+pub struct InMemory {
+    files: HashMap<PathBuf, Vec<u8>>,
+}
+
+impl InMemory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a file, replacing any already at `path`
+    pub fn insert(
+        &mut self,
+        path: impl AsRef<Path>,
+        contents: impl Into<Vec<u8>>,
+    ) {
+        self.files.insert(normalise(path.as_ref()), contents.into());
+    }
+
+    /// Adds a file, as `insert`, for building the files in one expression
+    pub fn with(
+        mut self,
+        path: impl AsRef<Path>,
+        contents: impl Into<Vec<u8>>,
+    ) -> Self {
+        self.insert(path, contents);
+        self
+    }
+}
+
+impl ResourceLoader for InMemory {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        self.files.get(&normalise(path)).cloned().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "no such file in memory")
+        })
+    }
+}
@@ -3,108 +3,371 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use crate::{
+    error::Error,
     layer::ObjectGroup,
-    parsers::{parse_color, parse_path, parse_property, parse_tileset_tiles},
+    load,
+    parsers::{parse_color, parse_property, parse_tileset_tiles},
     wangs::WangSet,
-    Color, TiledValue, Vec2,
+    writers::{is_default, write_property, write_tileset_tiles},
+    Color, FileSystem, ResourceLoader, TiledValue, Vec2,
 };
-use serde::{Deserialize};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
+/// A tileset is either embedded in the map (`Internal`) or a reference to a
+/// separate tileset file (`External`).
+///
+/// `Map::load_from_file` replaces every `External` with the `Internal` it
+/// references, so `External` is only seen when loading with
+/// `Map::load_from_file_unresolved` or `Map::load_from_str`.
+///
+/// A resolved tileset keeps its `source`, and is written as a reference to
+/// that file again rather than embedded.
 #[allow(clippy::large_enum_variant)]
-#[derive(Deserialize)]
-#[serde(untagged)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum TileSet {
     Internal(Internal),
     External(External),
 }
+
+// `source` decides which variant a tileset is
+impl<'de> Deserialize<'de> for TileSet {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Helper {
+            source: Option<PathBuf>,
+            columns: Option<u32>,
+            #[serde(rename = "firstgid")]
+            first_gid: Option<u32>,
+            image: Option<PathBuf>,
+            #[serde(rename = "imagewidth")]
+            image_width: Option<u32>,
+            #[serde(rename = "imageheight")]
+            image_height: Option<u32>,
+            #[serde(default)]
+            margin: u32,
+            #[serde(default)]
+            spacing: u32,
+            name: Option<String>,
+            #[serde(deserialize_with = "parse_property", default)]
+            properties: HashMap<String, TiledValue>,
+            terrains: Option<Vec<Terrain>>,
+            #[serde(rename = "tilecount")]
+            tile_count: Option<u32>,
+            #[serde(rename = "tileheight")]
+            tile_height: Option<u32>,
+            #[serde(rename = "tilewidth")]
+            tile_width: Option<u32>,
+            #[serde(rename = "tileoffset")]
+            tile_offset: Option<Vec2<i32>>,
+            #[serde(deserialize_with = "parse_tileset_tiles", default)]
+            tiles: HashMap<u32, Tile>,
+            #[serde(
+                rename = "transparentcolor",
+                deserialize_with = "parse_color",
+                default
+            )]
+            transparent_color: Color,
+            #[serde(rename = "wangsets")]
+            wang_sets: Option<Vec<WangSet>>,
+        }
+
+        fn required<T, E: de::Error>(
+            value: Option<T>,
+            field: &'static str,
+        ) -> Result<T, E> {
+            value.ok_or_else(|| E::missing_field(field))
+        }
+
+        let helper = Helper::deserialize(deserializer)?;
+        if let Some(source) = helper.source {
+            return Ok(TileSet::External(External {
+                first_gid: required(helper.first_gid, "firstgid")?,
+                source,
+            }));
+        }
+
+        // An image collection has no image of its own, and no columns
+        let columns = required(helper.columns, "columns")?;
+        let (image, image_width, image_height) = if columns == 0 {
+            (
+                helper.image.unwrap_or_default(),
+                helper.image_width.unwrap_or_default(),
+                helper.image_height.unwrap_or_default(),
+            )
+        } else {
+            (
+                required(helper.image, "image")?,
+                required(helper.image_width, "imagewidth")?,
+                required(helper.image_height, "imageheight")?,
+            )
+        };
+
+        Ok(TileSet::Internal(Internal {
+            columns,
+            first_gid: helper.first_gid.unwrap_or_default(),
+            image,
+            image_width,
+            image_height,
+            margin: helper.margin,
+            spacing: helper.spacing,
+            name: required(helper.name, "name")?,
+            properties: helper.properties,
+            terrains: helper.terrains,
+            tile_count: required(helper.tile_count, "tilecount")?,
+            tile_height: required(helper.tile_height, "tileheight")?,
+            tile_width: required(helper.tile_width, "tilewidth")?,
+            tile_offset: helper.tile_offset,
+            tiles: helper.tiles,
+            transparent_color: helper.transparent_color,
+            wang_sets: helper.wang_sets,
+            source: None,
+        }))
+    }
+}
+
+impl Serialize for TileSet {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            TileSet::Internal(Internal {
+                first_gid,
+                source: Some(source),
+                ..
+            }) => External {
+                first_gid: *first_gid,
+                source: source.clone(),
+            }
+            .serialize(serializer),
+            TileSet::Internal(internal) => internal.serialize(serializer),
+            TileSet::External(external) => external.serialize(serializer),
+        }
+    }
+}
+
 impl TileSet {
+    /// Returns the embedded tileset.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the tileset is an unresolved `External` reference.
     pub fn internal(&self) -> &Internal {
         match self {
-           TileSet::Internal(internal)  => internal,
-           _ => panic!("external tileset."),
+            TileSet::Internal(internal) => internal,
+            _ => panic!("external tileset."),
+        }
+    }
+
+    /// Returns the embedded tileset, or `None` for an unresolved reference
+    pub fn as_internal(&self) -> Option<&Internal> {
+        match self {
+            TileSet::Internal(internal) => Some(internal),
+            TileSet::External(_) => None,
+        }
+    }
+
+    /// GID corresponding to the first tile in the set
+    pub fn first_gid(&self) -> u32 {
+        match self {
+            TileSet::Internal(internal) => internal.first_gid,
+            TileSet::External(external) => external.first_gid,
         }
     }
- }
-#[derive(Deserialize)]
+}
+
+/// A reference to a tileset stored in its own file
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
 #[serde(rename_all = "lowercase")]
 pub struct External {
-    #[serde(rename(deserialize = "firstgid"))]
+    #[serde(rename = "firstgid")]
     pub first_gid: u32,
+    /// Path to the tileset file, relative to the map referencing it
     pub source: PathBuf,
 }
-#[derive(Deserialize)]
-#[derive(Debug, PartialEq, Clone)]
+
+impl External {
+    /// Loads the referenced tileset. `base_dir` is the directory of the map
+    /// the reference came from, as Tiled stores `source` relative to it.
+    ///
+    /// The returned tileset takes the `first_gid` of this reference, and
+    /// its image paths are rewritten to be relative to `base_dir` so they
+    /// can be used the same way as those of an embedded tileset.
+    pub fn load(&self, base_dir: &Path) -> Result<Internal, Error> {
+        self.load_with(base_dir, &FileSystem)
+    }
+
+    /// As `load`, reading the tileset, and any templates it uses, with
+    /// `loader`
+    pub fn load_with(
+        &self,
+        base_dir: &Path,
+        loader: &dyn ResourceLoader,
+    ) -> Result<Internal, Error> {
+        self.load_referenced(base_dir, None, String::new(), loader)
+    }
+
+    /// As `load_with`, with `file` and `json_path` locating the reference
+    /// for the error if the tileset can't be read
+    pub(crate) fn load_referenced(
+        &self,
+        base_dir: &Path,
+        file: Option<&Path>,
+        json_path: String,
+        loader: &dyn ResourceLoader,
+    ) -> Result<Internal, Error> {
+        let path = base_dir.join(&self.source);
+        let bytes = load::read_reference(loader, &path, file, json_path)?;
+        let mut tile_set: Internal = load::parse(&bytes, Some(&path), loader)?;
+        tile_set.first_gid = self.first_gid;
+        tile_set.source = Some(self.source.clone());
+
+        if let Some(source_dir) = self.source.parent() {
+            // An image collection has no image of its own
+            if !tile_set.image.as_os_str().is_empty() {
+                tile_set.image = source_dir.join(&tile_set.image);
+            }
+            for tile in tile_set.tiles.values_mut() {
+                if let Some(image) = &tile.image {
+                    let image = source_dir.join(image);
+                    tile.image = Some(image.to_string_lossy().into_owned());
+                }
+            }
+        }
+        Ok(tile_set)
+    }
+}
+
+/// An embedded tileset, or one loaded from its own file.
+///
+/// A tileset is either one image cut in to a grid of tiles, or an image
+/// collection, where each `Tile` has its own `image` and the tileset's
+/// `image` is empty and `columns` is 0.
+#[derive(Serialize, Debug, PartialEq, Clone)]
 pub struct Internal {
     /// The number of tile columns in the tileset. Eg; dividing the
     /// associated image in to columns where each column is the width
     /// of the tile.
     pub columns: u32,
     /// GID corresponding to the first tile in the set
-    #[serde(rename(deserialize = "firstgid"), default)]
+    #[serde(rename = "firstgid")]
     pub first_gid: u32,
-    /// Path to the image used for tiles in this set
-    #[serde(deserialize_with = "parse_path")]
+    /// Path to the image used for tiles in this set. Empty for an image
+    /// collection.
+    #[serde(skip_serializing_if = "is_default")]
     pub image: PathBuf,
-    #[serde(rename(deserialize = "imagewidth"))]
+    #[serde(rename = "imagewidth", skip_serializing_if = "is_default")]
     pub image_width: u32,
-    #[serde(rename(deserialize = "imageheight"))]
+    #[serde(rename = "imageheight", skip_serializing_if = "is_default")]
     pub image_height: u32,
     /// Buffer between image edge and first tile in pixels
-    #[serde(default)]
     pub margin: u32,
     /// Spacing between adjacent tiles in image in pixels
-    #[serde(default)]
     pub spacing: u32,
     pub name: String,
-    #[serde(deserialize_with = "parse_property", default)]
+    #[serde(
+        serialize_with = "write_property",
+        skip_serializing_if = "HashMap::is_empty"
+    )]
     pub properties: HashMap<String, TiledValue>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub terrains: Option<Vec<Terrain>>,
     /// The tile count + the first GID enable finding the tile location
     /// on the image
-    #[serde(rename(deserialize = "tilecount"))]
+    #[serde(rename = "tilecount")]
     pub tile_count: u32,
-    #[serde(rename(deserialize = "tileheight"))]
+    #[serde(rename = "tileheight")]
     pub tile_height: u32,
-    #[serde(rename(deserialize = "tilewidth"))]
+    #[serde(rename = "tilewidth")]
     pub tile_width: u32,
     /// used to specify an offset in pixels, to be applied
     /// when drawing a tile from this tileset
-    #[serde(rename(deserialize = "tileoffset"))]
+    #[serde(rename = "tileoffset", skip_serializing_if = "Option::is_none")]
     pub tile_offset: Option<Vec2<i32>>,
     /// Holds *extra* information for tiles such as terrain or animation
-    #[serde(deserialize_with = "parse_tileset_tiles", default)]
-    pub tiles: HashMap<u32, Tile>,
     #[serde(
-        rename(deserialize = "transparentcolor"),
-        deserialize_with = "parse_color",
-        default
+        serialize_with = "write_tileset_tiles",
+        skip_serializing_if = "HashMap::is_empty"
     )]
+    pub tiles: HashMap<u32, Tile>,
+    #[serde(rename = "transparentcolor", skip_serializing_if = "is_default")]
     /// Defaults to 0,0,0,0 (rgba)
     pub transparent_color: Color,
-    #[serde(rename(deserialize = "wangsets"))]
+    #[serde(rename = "wangsets", skip_serializing_if = "Option::is_none")]
     pub wang_sets: Option<Vec<WangSet>>,
+    /// The file the tileset was loaded from, relative to the map, if it was
+    /// an external tileset. A map writes the tileset as a reference to this
+    /// file, so set it to `None` to embed the tileset instead.
+    #[serde(skip)]
+    pub source: Option<PathBuf>,
+}
+
+// Read the same as an embedded `TileSet`, so both have the same fields
+// required, such as `image` unless it's an image collection
+impl<'de> Deserialize<'de> for Internal {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        match TileSet::deserialize(deserializer)? {
+            TileSet::Internal(internal) => Ok(internal),
+            TileSet::External(_) => Err(de::Error::custom(
+                "expected a tileset, found a reference to one",
+            )),
+        }
+    }
 }
 /// Contains all possible data for a tile including an optional `ObjectGroup`
-#[derive(Deserialize, Debug, PartialEq, Clone)]
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
 pub struct Tile {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub animation: Option<Vec<Frame>>,
     /// Unlike the ID used in the `TileLayer`, this ID is
     /// local to the `TileSet` only and so starts at 0 (the
     /// tile layer ID starts a 1 for tiles with 0 being no-tile).
     pub id: u32,
     /// Image representing this tile if it uses a separate image
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub image: Option<String>,
     /// Width of the tile image in pixels
-    #[serde(rename(deserialize = "imagewidth"), default)]
+    #[serde(
+        rename = "imagewidth",
+        default,
+        skip_serializing_if = "is_default"
+    )]
     pub image_width: u32,
     /// Height of the tile image in pixels
-    #[serde(rename(deserialize = "imageheight"), default)]
+    #[serde(
+        rename = "imageheight",
+        default,
+        skip_serializing_if = "is_default"
+    )]
     pub image_height: u32,
-    #[serde(rename(deserialize = "objectgroup"))]
+    /// The left of the part of `image` used for the tile, since Tiled 1.9
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub x: u32,
+    /// The top of the part of `image` used for the tile
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub y: u32,
+    /// The width of the part of `image` used for the tile, or all of it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    /// The height of the part of `image` used for the tile, or all of it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    #[serde(rename = "objectgroup", skip_serializing_if = "Option::is_none")]
     pub object_group: Option<ObjectGroup>,
-    #[serde(deserialize_with = "parse_property", default)]
+    #[serde(
+        deserialize_with = "parse_property",
+        serialize_with = "write_property",
+        default,
+        skip_serializing_if = "HashMap::is_empty"
+    )]
     pub properties: HashMap<String, TiledValue>,
     /// The order of indices is: top-left, top-right, bottom-left, bottom-right
     ///
@@ -112,23 +375,24 @@ pub struct Tile {
     /// specific terrain type for this tile. Typically used in conjunction
     /// with the tileset structure as the terrain tiles are stored within
     /// the data there.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub terrain: Option<[i8; 4]>,
     /// An optional string for describing a type
-    #[serde(rename(deserialize = "type"))]
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
     pub tile_type: Option<String>,
 }
 
 /// Data for an individual frame of animation
-#[derive(Deserialize, Debug, PartialEq, Eq, Clone)]
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone)]
 pub struct Frame {
     /// Frame duration in milliseconds
     pub duration: u32,
     /// Local tile ID representing this frame
-    #[serde(rename(deserialize = "tileid"))]
+    #[serde(rename = "tileid")]
     pub tile_id: u32,
 }
 
-#[derive(Deserialize, Debug, PartialEq, Eq, Clone)]
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone)]
 pub struct Terrain {
     pub name: String,
     /// Local ID of the tile for this terrain within the tileset
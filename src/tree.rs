@@ -0,0 +1,408 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Walking the tree of layers made by groups, with what each layer gets
+//! from the groups it is in worked out along the way.
+
+use crate::{Color, Layer, LayerType, Map, Object, Vec2};
+use std::collections::HashMap;
+use std::slice;
+use std::sync::OnceLock;
+
+/// A layer which isn't a group, with the properties it has once those of
+/// the groups it is in are applied. Returned by `Map::iter_layers`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct LayerInfo<'a> {
+    pub layer: &'a Layer,
+    /// The names of the groups the layer is in, outermost first
+    pub path: Vec<&'a str>,
+    /// The offsets of the layer and its groups added together
+    pub offset: Vec2<f32>,
+    /// The opacities of the layer and its groups multiplied together
+    pub opacity: f32,
+    /// False if the layer or any of its groups is hidden
+    pub visible: bool,
+    /// The tints of the layer and its groups multiplied together, if any
+    /// have one
+    pub tint_color: Option<Color>,
+    /// The parallax factors of the layer and its groups multiplied together
+    pub parallax: Vec2<f32>,
+}
+
+/// What the layers in a group get from it and the groups it is in
+#[derive(Clone)]
+struct Inherited<'a> {
+    path: Vec<&'a str>,
+    offset: Vec2<f32>,
+    opacity: f32,
+    visible: bool,
+    tint_color: Option<Color>,
+    parallax: Vec2<f32>,
+}
+
+impl<'a> Inherited<'a> {
+    /// What the top-level layers get, which is nothing
+    fn root() -> Self {
+        Inherited {
+            path: Vec::new(),
+            offset: Vec2 { x: 0.0, y: 0.0 },
+            opacity: 1.0,
+            visible: true,
+            tint_color: None,
+            parallax: Vec2 { x: 1.0, y: 1.0 },
+        }
+    }
+
+    /// Applies the properties of `layer`, which is in the group `self`
+    /// describes
+    fn apply(&self, layer: &'a Layer) -> Self {
+        let tint_color = match (&self.tint_color, &layer.tint_color) {
+            (Some(a), Some(b)) => Some(multiply(a, b)),
+            (a, b) => a.clone().or_else(|| b.clone()),
+        };
+        Inherited {
+            path: self.path.clone(),
+            offset: Vec2 {
+                x: self.offset.x + layer.offset_x,
+                y: self.offset.y + layer.offset_y,
+            },
+            opacity: self.opacity * layer.opacity,
+            visible: self.visible && layer.visible,
+            tint_color,
+            parallax: Vec2 {
+                x: self.parallax.x * layer.parallax_x,
+                y: self.parallax.y * layer.parallax_y,
+            },
+        }
+    }
+}
+
+/// Multiplies each channel, as Tiled does for the tint of a group's layers
+fn multiply(a: &Color, b: &Color) -> Color {
+    Color(
+        a.r() * b.r() / 255,
+        a.g() * b.g() / 255,
+        a.b() * b.b() / 255,
+        a.a() * b.a() / 255,
+    )
+}
+
+/// A depth-first iterator over the layers of a map which aren't groups,
+/// in the order they are drawn. Returned by `Map::iter_layers`.
+pub struct Layers<'a> {
+    /// The layers left in each group being walked, with the group
+    stack: Vec<(slice::Iter<'a, Layer>, Inherited<'a>)>,
+}
+
+impl<'a> Iterator for Layers<'a> {
+    type Item = LayerInfo<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (layers, group) = self.stack.last_mut()?;
+            let layer = match layers.next() {
+                Some(layer) => layer,
+                None => {
+                    self.stack.pop();
+                    continue;
+                }
+            };
+            let mut inherited = group.apply(layer);
+            match &layer.layer_type {
+                LayerType::Group { layers } => {
+                    inherited.path.push(&layer.name);
+                    self.stack.push((layers.iter(), inherited));
+                }
+                _ => {
+                    return Some(LayerInfo {
+                        layer,
+                        path: inherited.path,
+                        offset: inherited.offset,
+                        opacity: inherited.opacity,
+                        visible: inherited.visible,
+                        tint_color: inherited.tint_color,
+                        parallax: inherited.parallax,
+                    })
+                }
+            }
+        }
+    }
+}
+
+impl Map {
+    /// Iterates over every layer which isn't a group, depth-first, with
+    /// the offsets, opacity, visibility, tint and parallax of the groups it
+    /// is in applied
+    pub fn iter_layers(&self) -> Layers<'_> {
+        Layers {
+            stack: vec![(self.layers.iter(), Inherited::root())],
+        }
+    }
+}
+
+impl Map {
+    /// The first layer with the name, group or not, in the order they are
+    /// drawn
+    pub fn layer_by_name(&self, name: &str) -> Option<&Layer> {
+        let found = self.layer_index.get(&self.layers).layers_by_name.get(name);
+        match found.map(|path| layer_at(&self.layers, path)) {
+            Some(Some(layer)) if layer.name == name => Some(layer),
+            _ => find_layer(&self.layers, &|layer| layer.name == name),
+        }
+    }
+
+    /// The layer with the id, group or not
+    pub fn layer_by_id(&self, id: u32) -> Option<&Layer> {
+        let found = self.layer_index.get(&self.layers).layers_by_id.get(&id);
+        match found.map(|path| layer_at(&self.layers, path)) {
+            Some(Some(layer)) if layer.id == Some(id) => Some(layer),
+            _ => find_layer(&self.layers, &|layer| layer.id == Some(id)),
+        }
+    }
+
+    /// The layer at the path of names separated by `/`, starting from the
+    /// top-level layers, eg; `Group/Objects` for the layer named `Objects`
+    /// in the top-level group named `Group`. Where names are repeated,
+    /// this is the first layer the whole path matches.
+    pub fn layer_by_path(&self, path: &str) -> Option<&Layer> {
+        fn find<'a>(layers: &'a [Layer], names: &[&str]) -> Option<&'a Layer> {
+            let (name, rest) = names.split_first()?;
+            layers.iter().filter(|layer| layer.name == *name).find_map(
+                |layer| match (&layer.layer_type, rest) {
+                    (_, []) => Some(layer),
+                    (LayerType::Group { layers }, _) => find(layers, rest),
+                    _ => None,
+                },
+            )
+        }
+
+        let names: Vec<&str> = path.split('/').collect();
+        find(&self.layers, &names)
+    }
+
+    /// The object with the id, from any object group
+    pub fn object_by_id(&self, id: u32) -> Option<&Object> {
+        let found = self.layer_index.get(&self.layers).objects_by_id.get(&id);
+        match found.map(|place| object_at(&self.layers, place)) {
+            Some(Some(object)) if object.id == Some(id) => Some(object),
+            _ => self
+                .find_objects(|object| object.id == Some(id))
+                .into_iter()
+                .next(),
+        }
+    }
+
+    /// Every object with the name, from all object groups, in the order
+    /// they are drawn
+    pub fn objects_by_name(&self, name: &str) -> Vec<&Object> {
+        let index = self.layer_index.get(&self.layers);
+        self.objects_at(index.objects_by_name.get(name), |object| {
+            object.name == name
+        })
+    }
+
+    /// Every object with the type, from all object groups, in the order
+    /// they are drawn. An object without a `custom_type` has its `class`
+    /// as its type, as Tiled 1.9 wrote it there.
+    pub fn objects_by_type(&self, custom_type: &str) -> Vec<&Object> {
+        let index = self.layer_index.get(&self.layers);
+        self.objects_at(index.objects_by_type.get(custom_type), |object| {
+            type_of(object) == custom_type
+        })
+    }
+
+    /// Takes the next layer id, for a layer about to be added. It's
+    /// `next_layer_id` unless a layer already has that id or a greater
+    /// one, as can happen with maps edited by hand or by older versions of
+    /// Tiled.
+    pub fn new_layer_id(&mut self) -> u32 {
+        let mut used = 0;
+        visit(&self.layers, &mut Vec::new(), &mut |_, layer| {
+            used = used.max(layer.id.unwrap_or(0));
+        });
+        let id = self.next_layer_id.max(used + 1);
+        self.next_layer_id = id + 1;
+        id
+    }
+
+    /// Takes the next object id, for an object about to be added, as
+    /// `new_layer_id` does for layers
+    pub fn new_object_id(&mut self) -> u32 {
+        let mut used = 0;
+        visit(&self.layers, &mut Vec::new(), &mut |_, layer| {
+            if let LayerType::ObjectGroup(group) = &layer.layer_type {
+                for object in &group.objects {
+                    used = used.max(object.id.unwrap_or(0));
+                }
+            }
+        });
+        let id = self.next_object_id.max(used + 1);
+        self.next_object_id = id + 1;
+        id
+    }
+
+    /// The objects at the places the index gives, or those `matches`
+    /// finds if any place is out of date or the index has none
+    fn objects_at(
+        &self,
+        places: Option<&Vec<Place>>,
+        matches: impl Fn(&Object) -> bool,
+    ) -> Vec<&Object> {
+        let places = match places {
+            Some(places) => places,
+            None => return self.find_objects(matches),
+        };
+        let objects: Option<Vec<&Object>> = places
+            .iter()
+            .map(|place| object_at(&self.layers, place).filter(|o| matches(o)))
+            .collect();
+        objects.unwrap_or_else(|| self.find_objects(matches))
+    }
+
+    /// Every object `matches` finds, checking each in turn
+    fn find_objects(&self, matches: impl Fn(&Object) -> bool) -> Vec<&Object> {
+        let mut found = Vec::new();
+        visit(&self.layers, &mut Vec::new(), &mut |_, layer| {
+            if let LayerType::ObjectGroup(group) = &layer.layer_type {
+                found.extend(group.objects.iter().filter(|o| matches(o)));
+            }
+        });
+        found
+    }
+}
+
+/// Where to find layers and objects by name and id. It's built when a map
+/// is loaded, or on first use, and as a cache it never makes two maps
+/// unequal. Lookups check that what they find is what was asked for, and
+/// search the layers instead when it isn't or the index has nothing.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct LayerIndex(OnceLock<Locations>);
+
+impl LayerIndex {
+    fn get(&self, layers: &[Layer]) -> &Locations {
+        self.0.get_or_init(|| Locations::new(layers))
+    }
+
+    /// Builds the index now, rather than on first use
+    pub(crate) fn build(&self, layers: &[Layer]) {
+        self.get(layers);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.0 = OnceLock::new();
+    }
+}
+
+impl PartialEq for LayerIndex {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+/// The index of an object within its group, after the path to the group
+type Place = (Vec<usize>, usize);
+
+#[derive(Debug, Default, Clone)]
+struct Locations {
+    layers_by_id: HashMap<u32, Vec<usize>>,
+    /// The first layer with each name
+    layers_by_name: HashMap<String, Vec<usize>>,
+    objects_by_id: HashMap<u32, Place>,
+    objects_by_name: HashMap<String, Vec<Place>>,
+    objects_by_type: HashMap<String, Vec<Place>>,
+}
+
+impl Locations {
+    fn new(layers: &[Layer]) -> Self {
+        let mut index = Locations::default();
+        visit(layers, &mut Vec::new(), &mut |path, layer| {
+            if let Some(id) = layer.id {
+                index.layers_by_id.insert(id, path.to_vec());
+            }
+            index
+                .layers_by_name
+                .entry(layer.name.clone())
+                .or_insert_with(|| path.to_vec());
+            let objects = match &layer.layer_type {
+                LayerType::ObjectGroup(group) => &group.objects,
+                _ => return,
+            };
+            for (i, object) in objects.iter().enumerate() {
+                let place = (path.to_vec(), i);
+                if let Some(id) = object.id {
+                    index.objects_by_id.insert(id, place.clone());
+                }
+                index
+                    .objects_by_name
+                    .entry(object.name.clone())
+                    .or_default()
+                    .push(place.clone());
+                index
+                    .objects_by_type
+                    .entry(type_of(object).to_string())
+                    .or_default()
+                    .push(place);
+            }
+        });
+        index
+    }
+}
+
+/// The type of an object, which is its class if it doesn't have one
+fn type_of(object: &Object) -> &str {
+    if object.custom_type.is_empty() {
+        &object.class
+    } else {
+        &object.custom_type
+    }
+}
+
+/// Calls `f` with every layer, groups before the layers in them, and the
+/// indices leading to it through `layers` and its groups
+fn visit<'a>(
+    layers: &'a [Layer],
+    path: &mut Vec<usize>,
+    f: &mut impl FnMut(&[usize], &'a Layer),
+) {
+    for (i, layer) in layers.iter().enumerate() {
+        path.push(i);
+        f(path, layer);
+        if let LayerType::Group { layers } = &layer.layer_type {
+            visit(layers, path, f);
+        }
+        path.pop();
+    }
+}
+
+fn find_layer<'a>(
+    layers: &'a [Layer],
+    matches: &impl Fn(&Layer) -> bool,
+) -> Option<&'a Layer> {
+    let mut found = None;
+    visit(layers, &mut Vec::new(), &mut |_, layer| {
+        if found.is_none() && matches(layer) {
+            found = Some(layer);
+        }
+    });
+    found
+}
+
+fn layer_at<'a>(layers: &'a [Layer], path: &[usize]) -> Option<&'a Layer> {
+    let (last, groups) = path.split_last()?;
+    let mut layers = layers;
+    for &i in groups {
+        match &layers.get(i)?.layer_type {
+            LayerType::Group { layers: inner } => layers = inner,
+            _ => return None,
+        }
+    }
+    layers.get(*last)
+}
+
+fn object_at<'a>(layers: &'a [Layer], place: &Place) -> Option<&'a Object> {
+    match &layer_at(layers, &place.0)?.layer_type {
+        LayerType::ObjectGroup(group) => group.objects.get(place.1),
+        _ => None,
+    }
+}
@@ -1,117 +1,544 @@
-use crate::{map::Map, TileLayer, TileRect, Vec2};
-use std::fs::File;
-use std::io::Error;
+use crate::{
+    error::Error, load, map::Map, writers, Chunk, ChunkSize, Color, FileSystem,
+    Gid, Internal, Layer, ResourceLoader, Tile, TileInfo, TileLayer, TileRect,
+    TileRegion, TileSet, Vec2, Version,
+};
+use serde::Serialize;
 use std::path::Path;
 
 impl Map {
+    /// Loads a map and any external tilesets and object templates it
+    /// references. Their paths are resolved relative to the directory
+    /// containing the map.
     pub fn load_from_file(path: &Path) -> Result<Self, Error> {
-        let file = File::open(path)?;
-        let set = serde_json::from_reader(file)?;
-        Ok(set)
+        Self::load_with(path, &FileSystem)
     }
 
+    /// As `load_from_file`, reading the map and everything it references
+    /// with `loader` rather than from the filesystem
+    pub fn load_with(
+        path: &Path,
+        loader: &dyn ResourceLoader,
+    ) -> Result<Self, Error> {
+        let mut map = Self::load_unresolved_with(path, loader)?;
+        let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+        load::resolve_tile_sets(&mut map, base_dir, Some(path), loader)?;
+        Ok(map)
+    }
+
+    /// Loads a map, leaving references to external tilesets as
+    /// `TileSet::External`. Object templates are still applied.
+    pub fn load_from_file_unresolved(path: &Path) -> Result<Self, Error> {
+        Self::load_unresolved_with(path, &FileSystem)
+    }
+
+    /// As `load_from_file_unresolved`, reading with `loader`
+    pub fn load_unresolved_with(
+        path: &Path,
+        loader: &dyn ResourceLoader,
+    ) -> Result<Self, Error> {
+        let bytes = loader.read(path).map_err(|source| Error::Io {
+            file: path.to_path_buf(),
+            source,
+        })?;
+        let map: Self = load::parse(&bytes, Some(path), loader)?;
+        map.layer_index.build(&map.layers);
+        Ok(map)
+    }
+
+    /// Loads a map from a string. As there is no file to be relative to,
+    /// external tilesets are left unresolved; see `resolve_tile_sets`. So
+    /// are object templates, as `ObjectType::Template`.
     pub fn load_from_str(s: &str) -> Result<Self, Error> {
-        let set = serde_json::from_str(s)?;
-        Ok(set)
-    }
-
-    /// Returns the image path for the image the tile is using
-    pub fn tileset_image_path(&self, tile_gid: u32) -> Option<&Path> {
-        for set in &self.tile_sets {
-            let set = set.internal();
-            if tile_gid >= set.first_gid
-                && tile_gid < set.tile_count + set.first_gid
-            {
-                return Some(set.image.as_path());
-            }
+        let map: Self = load::parse(s.as_bytes(), None, &FileSystem)?;
+        map.layer_index.build(&map.layers);
+        Ok(map)
+    }
+
+    /// Replaces every `TileSet::External` with the tileset it references,
+    /// loaded from `base_dir`, keeping the `first_gid` given by the map
+    pub fn resolve_tile_sets(&mut self, base_dir: &Path) -> Result<(), Error> {
+        self.resolve_tile_sets_with(base_dir, &FileSystem)
+    }
+
+    /// As `resolve_tile_sets`, reading the tilesets with `loader`
+    pub fn resolve_tile_sets_with(
+        &mut self,
+        base_dir: &Path,
+        loader: &dyn ResourceLoader,
+    ) -> Result<(), Error> {
+        load::resolve_tile_sets(self, base_dir, None, loader)
+    }
+
+    /// Writes the map as Tiled JSON. Tile layers keep their encoding and
+    /// compression, and resolved external tilesets are written as
+    /// references to their files again.
+    pub fn to_string(&self) -> Result<String, Error> {
+        self.write(None)
+    }
+
+    /// Writes the map to a file as Tiled JSON, as `to_string`
+    pub fn save_to_file(&self, path: &Path) -> Result<(), Error> {
+        let json = self.write(Some(path))?;
+        std::fs::write(path, json).map_err(|source| Error::Io {
+            file: path.to_path_buf(),
+            source,
+        })
+    }
+
+    fn write(&self, file: Option<&Path>) -> Result<String, Error> {
+        // Tiled looks for the type to know the file is a map
+        #[derive(Serialize)]
+        struct Document<'a> {
+            #[serde(rename = "type")]
+            kind: &'static str,
+            #[serde(flatten)]
+            map: &'a Map,
         }
-        None
+
+        let mut json = Vec::new();
+        let document = Document {
+            kind: "map",
+            map: self,
+        };
+        writers::with_compression_level(self.compression_level, || {
+            serde_path_to_error::serialize(
+                &document,
+                &mut serde_json::Serializer::new(&mut json),
+            )
+        })
+        .map_err(|e| Error::serialize(file, e))?;
+        // serde_json only writes valid UTF-8
+        Ok(String::from_utf8(json).expect("JSON is UTF-8"))
+    }
+
+    /// Returns the image path for the image the tile is using. Flip flags
+    /// on the GID are ignored.
+    pub fn tileset_image_path(
+        &self,
+        tile_gid: impl Into<Gid>,
+    ) -> Option<&Path> {
+        self.tileset_of(tile_gid.into())
+            .map(|set| set.image.as_path())
     }
 
     /// Return the name of the tileset the tile is from
-    pub fn tileset_name(&self, tile_gid: u32) -> Option<&str> {
-        for set in &self.tile_sets {
-            let set = set.internal();
-            if tile_gid >= set.first_gid
-                && tile_gid < set.tile_count + set.first_gid
-            {
-                return Some(&set.name);
-            }
-        }
-        None
+    pub fn tileset_name(&self, tile_gid: impl Into<Gid>) -> Option<&str> {
+        self.tileset_of(tile_gid.into())
+            .map(|set| set.name.as_str())
+    }
+
+    /// Returns the image the tile is drawn from: the tileset's image, or
+    /// the tile's own in an image collection. Flip flags on the GID are
+    /// ignored.
+    pub fn tile_image_path(&self, tile_gid: impl Into<Gid>) -> Option<&Path> {
+        let tile_gid = tile_gid.into();
+        let set = self.tileset_of(tile_gid)?;
+        set.tile_image_path(set.lid(tile_gid)?)
     }
 
-    /// Returns the position and dimensions of the tile GID on its associated image.
+    /// Returns the position and dimensions of the tile GID on the image
+    /// given by `tile_image_path`, or `None` if no loaded tileset has it.
     /// Used for drawing tiles, eg; using SDL2 to blit this tile from an image surface.
-    pub fn tile_position_on_image(&self, mut tile_gid: u32) -> TileRect {
-        let mut tileset = self.tile_sets[0].internal();
-        for set in &self.tile_sets {
-            let set = set.internal();
-            if tile_gid >= set.first_gid
-                && tile_gid < set.tile_count + set.first_gid
-            {
-                tileset = set;
-                tile_gid -= set.first_gid;
-                break;
-            }
-        }
-        tileset.tile_position_on_image(tile_gid)
-    }
-
-    /// Returns the tile position in pixels on the current map
-    pub fn tile_position_on_map(&self, count: u32, tile_gid: u32) -> Vec2<u32> {
-        let mut tileset = self.tile_sets[0].internal();
-        for set in &self.tile_sets {
-            let set = set.internal();
-            if tile_gid >= set.first_gid
-                && tile_gid < set.tile_count + set.first_gid
-            {
-                tileset = set;
-                break;
+    pub fn tile_position_on_image(
+        &self,
+        tile_gid: impl Into<Gid>,
+    ) -> Option<TileRect> {
+        let tile_gid = tile_gid.into();
+        let set = self.tileset_of(tile_gid)?;
+        set.tile_position_on_image(set.lid(tile_gid)?)
+    }
+
+    /// Returns the pixel position on the map to draw the tile at, given
+    /// its index in a tile layer's `data`. As in Tiled, a tile taller than
+    /// the map's grid is aligned to the bottom of its cell, and moved by
+    /// its tileset's `tile_offset`.
+    pub fn tile_position_on_map(
+        &self,
+        count: u32,
+        tile_gid: impl Into<Gid>,
+    ) -> Vec2<i32> {
+        let cell = self.tile_to_pixel(Vec2 {
+            x: (count % self.width) as i32,
+            y: (count / self.width) as i32,
+        });
+        let mut x = cell.x as i32;
+        let mut y = cell.y as i32;
+        if let Some(set) = self.tileset_of(tile_gid.into()) {
+            y += self.tile_height as i32 - set.tile_height as i32;
+            if let Some(offset) = &set.tile_offset {
+                x += offset.x;
+                y += offset.y;
             }
         }
-        let x = count % self.width * tileset.tile_width;
-        let y = count / self.width * tileset.tile_width;
         Vec2 { x, y }
     }
+
+    /// Finds the tileset a GID belongs to, returning it with the tile's
+    /// local ID and its extra details, if it has any. Flip flags on the GID
+    /// are ignored, and unresolved `TileSet::External`s are never found.
+    ///
+    /// This is a binary search of the tilesets by first GID, using an index
+    /// built the first time it's needed.
+    pub fn resolve_gid(
+        &self,
+        tile_gid: impl Into<Gid>,
+    ) -> Option<(&Internal, u32, Option<&Tile>)> {
+        let tile_gid = tile_gid.into();
+        let set = self.tileset_of(tile_gid)?;
+        let lid = set.lid(tile_gid)?;
+        Some((set, lid, set.get_tile_from_lid(lid)))
+    }
+
+    /// Returns everything about the tile the GID refers to, or `None` if
+    /// no loaded tileset has it
+    pub fn tile_info(&self, tile_gid: impl Into<Gid>) -> Option<TileInfo<'_>> {
+        let gid = tile_gid.into();
+        let (tile_set, local_id, tile) = self.resolve_gid(gid)?;
+        Some(TileInfo {
+            gid,
+            tile_set,
+            local_id,
+            image: tile_set.tile_image_path(local_id),
+            rect: tile_set.tile_position_on_image(local_id),
+            flip_h: gid.flip_h(),
+            flip_v: gid.flip_v(),
+            flip_d: gid.flip_d(),
+            rotate_hex120: gid.rotate_hex120(),
+            tile,
+            properties: tile.map(|tile| &tile.properties),
+            tile_type: tile.and_then(|tile| tile.tile_type.as_deref()),
+            animation: tile.and_then(|tile| tile.animation.as_deref()),
+            object_group: tile.and_then(|tile| tile.object_group.as_ref()),
+        })
+    }
+
+    /// The version of the file format, or `None` if the map doesn't give
+    /// one it can be read from
+    pub fn format_version(&self) -> Option<Version> {
+        Version::parse(&self.version)
+    }
+
+    /// The size of the chunks new tiles go in on an infinite map, from its
+    /// editor settings, else Tiled's default of 16 by 16
+    pub fn chunk_size(&self) -> ChunkSize {
+        self.editor_settings
+            .as_ref()
+            .and_then(|settings| settings.chunk_size)
+            .unwrap_or_default()
+    }
+
+    /// The version of Tiled which saved the map, if it says. Development
+    /// builds, which are versioned by date, give the date as the version.
+    pub fn editor_version(&self) -> Option<Version> {
+        self.tiled_version.as_deref().and_then(Version::parse)
+    }
+
+    /// Rebuilds the indices `resolve_gid`, `layer_by_name` and the other
+    /// lookups use. Call this after changing `tile_sets`, `layers` or the
+    /// objects in them. Until then lookups search each in turn when the
+    /// index is out of date or has nothing for them, and `layer_by_name`
+    /// and `objects_by_*` can miss what was added with the same name or
+    /// type as something already indexed.
+    pub fn reindex(&mut self) {
+        self.tile_set_index.clear();
+        self.layer_index.clear();
+    }
+
+    /// The loaded tileset holding the tile, if any
+    fn tileset_of(&self, tile_gid: Gid) -> Option<&Internal> {
+        match self.tile_set_index.find(&self.tile_sets, tile_gid.id()) {
+            Some(found) => found
+                .and_then(|i| self.tile_sets[i].as_internal())
+                .filter(|set| set.has_tile(tile_gid)),
+            None => self
+                .tile_sets
+                .iter()
+                .filter_map(TileSet::as_internal)
+                .find(|set| set.has_tile(tile_gid)),
+        }
+    }
 }
 
-impl crate::tile_set::Internal {
+impl Internal {
+    /// True if each tile has its own image, rather than being part of the
+    /// tileset's
+    pub fn is_image_collection(&self) -> bool {
+        self.columns == 0
+    }
+
+    /// Returns the image the tile is drawn from, or `None` if the tileset
+    /// has no such tile
+    pub fn tile_image_path(&self, local_id: u32) -> Option<&Path> {
+        if self.is_image_collection() {
+            let image = self.get_tile_from_lid(local_id)?.image.as_ref()?;
+            Some(Path::new(image))
+        } else if local_id < self.tile_count {
+            Some(&self.image)
+        } else {
+            None
+        }
+    }
+
     /// Returns the tile position and extents for it's location
     /// on the source image. Useful for creating textures/blits.
-    pub fn tile_position_on_image(&self, local_id: u32) -> TileRect {
-        let min_x = local_id % self.columns * self.tile_width;
-        let min_y = local_id / self.columns * self.tile_width;
-        TileRect {
-            x: min_x as i32,
-            y: min_y as i32,
+    ///
+    /// The margin around the image and spacing between tiles are skipped.
+    /// In an image collection the tile's own image is the source, or just
+    /// part of it when the tile gives one. `None` if the tileset has no
+    /// such tile.
+    pub fn tile_position_on_image(&self, local_id: u32) -> Option<TileRect> {
+        if self.is_image_collection() {
+            let tile = self.get_tile_from_lid(local_id)?;
+            tile.image.as_ref()?;
+            return Some(TileRect {
+                x: tile.x as i32,
+                y: tile.y as i32,
+                width: tile.width.unwrap_or(tile.image_width),
+                height: tile.height.unwrap_or(tile.image_height),
+            });
+        }
+        if local_id >= self.tile_count {
+            return None;
+        }
+        let column = local_id % self.columns;
+        let row = local_id / self.columns;
+        Some(TileRect {
+            x: (self.margin + column * (self.tile_width + self.spacing)) as i32,
+            y: (self.margin + row * (self.tile_height + self.spacing)) as i32,
             width: self.tile_width,
             height: self.tile_height,
-        }
+        })
     }
-    pub fn get_tile(&self, gid: u32) -> Option<&crate::Tile> {
-        self.get_tile_from_lid(self.lid(gid))
+    /// The tile's extra details, if it has any. Flip flags on the GID are
+    /// ignored, as they are by `has_tile` and `lid`.
+    pub fn get_tile(&self, gid: impl Into<Gid>) -> Option<&Tile> {
+        self.get_tile_from_lid(self.lid(gid)?)
     }
-    pub fn get_tile_from_lid(&self, lid: u32) -> Option<&crate::Tile> {
+    pub fn get_tile_from_lid(&self, lid: u32) -> Option<&Tile> {
         self.tiles.get(&lid)
     }
-    pub fn has_tile(&self, gid: u32) -> bool {
-        gid >= self.first_gid && gid < self.tile_count + self.first_gid
+    /// True if the tile is in this tileset. The IDs of an image
+    /// collection can have gaps, so only those with a `Tile` count.
+    pub fn has_tile(&self, gid: impl Into<Gid>) -> bool {
+        let gid = gid.into().id();
+        if gid < self.first_gid {
+            false
+        } else if self.is_image_collection() {
+            self.tiles.contains_key(&(gid - self.first_gid))
+        } else {
+            gid < self.tile_count + self.first_gid
+        }
     }
-    pub fn lid(&self, gid: u32) -> u32 {
-        gid - self.first_gid
+    /// The local ID of a GID, or `None` if it's before this tileset's,
+    /// such as the empty GID 0
+    pub fn lid(&self, gid: impl Into<Gid>) -> Option<u32> {
+        gid.into().id().checked_sub(self.first_gid)
     }
     pub fn gid(&self, lid: u32) -> u32 {
         lid + self.first_gid
     }
 }
 
+impl Layer {
+    /// The colour to tint the layer with, white if it has no tint
+    pub fn tint(&self) -> Color {
+        self.tint_color.clone().unwrap_or(Color(255, 255, 255, 255))
+    }
+}
+
 impl TileLayer {
     /// Returns the tiles position in tile column/row. To get a pixel dimension
-    /// multiply this by the tile dimensions
+    /// multiply this by the tile dimensions. `count` is an index in to
+    /// `data`, so this doesn't apply to the chunks of an infinite map.
     pub fn tile_position_on_layer(&self, count: u32) -> Vec2<u32> {
         let x = count % self.width;
         let y = count / self.width;
         Vec2 { x, y }
     }
+
+    /// Returns the GID at the tile column/row, or `None` if it is outside
+    /// of the layer. The tiles of an infinite map are found in its chunks.
+    pub fn get(&self, x: i32, y: i32) -> Option<Gid> {
+        if let Some(chunks) = &self.chunks {
+            let chunk = chunks.iter().find(|chunk| {
+                x >= chunk.x
+                    && y >= chunk.y
+                    && x - chunk.x < chunk.width as i32
+                    && y - chunk.y < chunk.height as i32
+            })?;
+            let index = (y - chunk.y) * chunk.width as i32 + (x - chunk.x);
+            return chunk.data.get(index as usize).copied();
+        }
+        if x < 0 || y < 0 || x as u32 >= self.width || y as u32 >= self.height {
+            return None;
+        }
+        self.data
+            .get((y as u32 * self.width + x as u32) as usize)
+            .copied()
+    }
+
+    /// Sets the GID at the tile column/row, returning the one replaced, or
+    /// `None` if the position is outside of a fixed-size layer. An
+    /// infinite map gets a new chunk, aligned to its size, if no chunk
+    /// covers the position. It's the size of the layer's other chunks, as
+    /// Tiled makes them all the same, or Tiled's default of 16x16 for the
+    /// first.
+    pub fn set(&mut self, x: i32, y: i32, gid: impl Into<Gid>) -> Option<Gid> {
+        let chunk_size = self
+            .chunks
+            .as_ref()
+            .and_then(|chunks| chunks.first())
+            .map_or_else(ChunkSize::default, |chunk| ChunkSize {
+                width: chunk.width,
+                height: chunk.height,
+            });
+        self.set_in_new_chunk(x, y, gid, chunk_size)
+    }
+
+    /// As `set`, with any new chunk of `chunk_size`, such as the map's
+    /// from `Map::chunk_size`
+    pub fn set_in_new_chunk(
+        &mut self,
+        x: i32,
+        y: i32,
+        gid: impl Into<Gid>,
+        chunk_size: ChunkSize,
+    ) -> Option<Gid> {
+        let gid = gid.into();
+        if let Some(chunks) = &mut self.chunks {
+            let found = chunks.iter().position(|chunk| {
+                x >= chunk.x
+                    && y >= chunk.y
+                    && x - chunk.x < chunk.width as i32
+                    && y - chunk.y < chunk.height as i32
+            });
+            let chunk = match found {
+                Some(i) => &mut chunks[i],
+                None => {
+                    let width = chunk_size.width.max(1);
+                    let height = chunk_size.height.max(1);
+                    chunks.push(Chunk {
+                        data: vec![Gid::default(); (width * height) as usize],
+                        height,
+                        width,
+                        x: x.div_euclid(width as i32) * width as i32,
+                        y: y.div_euclid(height as i32) * height as i32,
+                    });
+                    chunks.last_mut().unwrap()
+                }
+            };
+            let index = (y - chunk.y) * chunk.width as i32 + (x - chunk.x);
+            let cell = chunk.data.get_mut(index as usize)?;
+            return Some(std::mem::replace(cell, gid));
+        }
+        if x < 0 || y < 0 || x as u32 >= self.width || y as u32 >= self.height {
+            return None;
+        }
+        let index = (y as u32 * self.width + x as u32) as usize;
+        let cell = self.data.get_mut(index)?;
+        Some(std::mem::replace(cell, gid))
+    }
+
+    /// Iterates over the GIDs of a row, across the layer's `bounds`. Empty
+    /// tiles are included, as are the gaps between chunks.
+    pub fn row(&self, y: i32) -> impl Iterator<Item = Gid> + '_ {
+        let bounds = self.bounds();
+        (bounds.x..bounds.x + bounds.width as i32)
+            .map(move |x| self.get(x, y).unwrap_or_default())
+    }
+
+    /// Iterates over the GIDs of a column, as `row`
+    pub fn column(&self, x: i32) -> impl Iterator<Item = Gid> + '_ {
+        let bounds = self.bounds();
+        (bounds.y..bounds.y + bounds.height as i32)
+            .map(move |y| self.get(x, y).unwrap_or_default())
+    }
+
+    /// A view of the tiles within `area`, which may extend past the layer
+    pub fn region(&self, area: TileRect) -> TileRegion<'_> {
+        TileRegion { layer: self, area }
+    }
+
+    /// Iterates over the tiles of the layer which aren't empty, as
+    /// `(x, y, gid)`. The tiles of an infinite map are found in its chunks,
+    /// and can be at negative positions.
+    pub fn iter_cells(&self) -> impl Iterator<Item = (i32, i32, Gid)> + '_ {
+        let width = self.width.max(1) as usize;
+        // An infinite map's `data` is empty, but ignore it to match `get`
+        let data = match self.chunks {
+            Some(_) => &[][..],
+            None => &self.data[..],
+        };
+        let cells = data.iter().enumerate().map(move |(i, &gid)| {
+            ((i % width) as i32, (i / width) as i32, gid)
+        });
+        let chunk_cells = self.chunks.iter().flatten().flat_map(|chunk| {
+            let width = chunk.width.max(1) as usize;
+            chunk.data.iter().enumerate().map(move |(i, &gid)| {
+                (
+                    chunk.x + (i % width) as i32,
+                    chunk.y + (i / width) as i32,
+                    gid,
+                )
+            })
+        });
+        cells
+            .chain(chunk_cells)
+            .filter(|&(_, _, gid)| !gid.is_empty())
+    }
+
+    /// The tiles covered by the layer. For an infinite map this is the
+    /// smallest rectangle holding all of its chunks, which is empty if it
+    /// has none.
+    pub fn bounds(&self) -> TileRect {
+        let chunks = match &self.chunks {
+            Some(chunks) if !chunks.is_empty() => chunks,
+            Some(_) => return TileRect::default(),
+            None => {
+                return TileRect {
+                    x: 0,
+                    y: 0,
+                    width: self.width,
+                    height: self.height,
+                }
+            }
+        };
+        let left = chunks.iter().map(|c| c.x).min().unwrap_or(0);
+        let top = chunks.iter().map(|c| c.y).min().unwrap_or(0);
+        let right = chunks.iter().map(|c| c.x + c.width as i32).max();
+        let bottom = chunks.iter().map(|c| c.y + c.height as i32).max();
+        TileRect {
+            x: left,
+            y: top,
+            width: (right.unwrap_or(0) - left) as u32,
+            height: (bottom.unwrap_or(0) - top) as u32,
+        }
+    }
+
+    /// Returns the layer with its tiles in `data` rather than chunks, so it
+    /// can be used as a fixed-size layer. The tile at `x, y` is moved to
+    /// `x - bounds.x, y - bounds.y`, where `bounds` is from `bounds`. A
+    /// layer without chunks is returned as it is.
+    pub fn densify(&self) -> TileLayer {
+        if self.chunks.is_none() {
+            return self.clone();
+        }
+        let bounds = self.bounds();
+        let mut data =
+            vec![Gid::default(); (bounds.width * bounds.height) as usize];
+        for (x, y, gid) in self.iter_cells() {
+            let x = (x - bounds.x) as u32;
+            let y = (y - bounds.y) as u32;
+            data[(y * bounds.width + x) as usize] = gid;
+        }
+        TileLayer {
+            chunks: None,
+            data,
+            encoding: self.encoding.clone(),
+            compression: self.compression,
+            height: bounds.height,
+            width: bounds.width,
+        }
+    }
 }
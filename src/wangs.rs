@@ -3,7 +3,7 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use crate::{parsers::parse_color, Color};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 /// Data set for `Wang` tiles
 ///
@@ -13,45 +13,58 @@ use serde::Deserialize;
 /// when filling, or brushing to allow for smooth, non-repetitive transitions
 /// between tiles. In most cases this tiling is random, and based on color
 /// probability.
-#[derive(Deserialize, Debug, PartialEq, Clone)]
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
 pub struct WangSet {
-    #[serde(rename(deserialize = "cornercolors"))]
+    /// Colors of the Wang set, since Tiled 1.5
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub colors: Vec<WangColor>,
+    /// Corner colors, before Tiled 1.5
+    #[serde(
+        rename = "cornercolors",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
     pub corner_colors: Vec<WangColor>,
-    #[serde(rename(deserialize = "edgecolors"))]
+    /// Edge colors, before Tiled 1.5
+    #[serde(
+        rename = "edgecolors",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
     pub edge_colors: Vec<WangColor>,
     pub name: String,
-    /// Local ID of tile representing the Wang set
-    pub tile: u32,
-    #[serde(rename(deserialize = "wangtiles"))]
+    /// Local ID of tile representing the Wang set, or -1 for none
+    pub tile: i32,
+    #[serde(rename = "wangtiles")]
     pub wang_tiles: Vec<WangTile>,
 }
 
-#[derive(Deserialize, Debug, PartialEq, Clone)]
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
 pub struct WangColor {
     #[serde(deserialize_with = "parse_color")]
     pub color: Color,
     pub name: String,
     /// Probability used when randomizing
     pub probability: f32,
-    /// Local ID of tile representing the Wang color
-    pub tile: u32,
+    /// Local ID of tile representing the Wang color, or -1 for none
+    pub tile: i32,
 }
 
-#[derive(Deserialize, Debug, PartialEq, Eq, Clone)]
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone)]
 pub struct WangTile {
     /// Tile is flipped diagonally
-    #[serde(rename(deserialize = "dflip"))]
+    #[serde(rename = "dflip")]
     pub d_flip: bool,
     /// Tile is flipped horizontally
-    #[serde(rename(deserialize = "hflip"))]
+    #[serde(rename = "hflip")]
     pub h_flip: bool,
     /// Tile is flipped vertically
-    #[serde(rename(deserialize = "vflip"))]
+    #[serde(rename = "vflip")]
     pub v_flip: bool,
     /// Local ID of tile
-    #[serde(rename(deserialize = "tileid"))]
+    #[serde(rename = "tileid")]
     pub tile_id: u32,
     /// Array of Wang color indexes
-    #[serde(rename(deserialize = "wangid"))]
+    #[serde(rename = "wangid")]
     pub wang_id: Vec<u8>,
 }
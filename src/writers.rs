@@ -0,0 +1,217 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! The counterparts of `parsers`, putting values back in to the shape
+//! Tiled writes them in.
+
+use crate::{Color, Compression, Gid, TiledValue};
+use serde::ser::{self, SerializeSeq};
+use serde::{Serialize, Serializer};
+use std::cell::Cell;
+use std::collections::HashMap;
+
+thread_local! {
+    /// The level `compress` uses, which `Map` sets while it's written
+    static COMPRESSION_LEVEL: Cell<i32> = const { Cell::new(-1) };
+}
+
+/// Runs `write` with tile data compressed at `level`, where -1 leaves it
+/// to the codec
+pub fn with_compression_level<T>(level: i32, write: impl FnOnce() -> T) -> T {
+    // Put the previous level back even if `write` panics
+    struct Restore(i32);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            COMPRESSION_LEVEL.with(|level| level.set(self.0));
+        }
+    }
+
+    let _restore = Restore(COMPRESSION_LEVEL.with(|l| l.replace(level)));
+    write()
+}
+
+pub fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
+/// For fields defaulting to `parsers::one`
+pub fn is_one(value: &f32) -> bool {
+    *value == 1.0
+}
+
+/// For fields defaulting to `parsers::default_compression_level`
+pub fn is_default_compression_level(value: &i32) -> bool {
+    *value == -1
+}
+
+pub fn write_tileset_tiles<S>(
+    tiles: &HashMap<u32, crate::Tile>,
+    ser: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    let mut ids: Vec<&u32> = tiles.keys().collect();
+    ids.sort();
+    let mut seq = ser.serialize_seq(Some(ids.len()))?;
+    for id in ids {
+        seq.serialize_element(&tiles[id])?;
+    }
+    seq.end()
+}
+
+pub fn write_property<S>(
+    properties: &HashMap<String, TiledValue>,
+    ser: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    #[derive(Serialize)]
+    struct Helper<'a> {
+        name: &'a str,
+        #[serde(flatten)]
+        value: &'a TiledValue,
+    }
+
+    // Tiled keeps properties sorted by name
+    let mut names: Vec<&String> = properties.keys().collect();
+    names.sort();
+    let mut seq = ser.serialize_seq(Some(names.len()))?;
+    for name in names {
+        seq.serialize_element(&Helper {
+            name,
+            value: &properties[name],
+        })?;
+    }
+    seq.end()
+}
+
+/// Formats a colour as `#RRGGBB`, or `#AARRGGBB` if it isn't opaque
+pub fn write_color<S>(color: &Color, ser: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    let mut hex = String::from("#");
+    let channels = if color.a() == 255 {
+        [color.r(), color.g(), color.b()].to_vec()
+    } else {
+        [color.a(), color.r(), color.g(), color.b()].to_vec()
+    };
+    for channel in channels {
+        hex.push_str(&write_hex(channel).ok_or_else(|| {
+            ser::Error::custom(format!(
+                "colour channel {} is greater than 255",
+                channel
+            ))
+        })?);
+    }
+    ser.serialize_str(&hex)
+}
+
+/// A colour channel as two hex digits
+fn write_hex(channel: u32) -> Option<String> {
+    if channel > 255 {
+        return None;
+    }
+    Some(format!("{:02x}", channel))
+}
+
+/// Converts GIDs to little-endian bytes, compresses them if asked to, and
+/// encodes the result as base64
+pub fn encode_data(
+    gids: &[Gid],
+    compression: Option<&Compression>,
+) -> Result<String, String> {
+    let mut bytes = Vec::with_capacity(gids.len() * 4);
+    for gid in gids {
+        bytes.extend_from_slice(&gid.raw().to_le_bytes());
+    }
+    let bytes = match compression {
+        Some(compression) => compress(&bytes, compression)?,
+        None => bytes,
+    };
+    Ok(base64::encode(&bytes))
+}
+
+#[cfg_attr(
+    not(any(feature = "zlib", feature = "gzip", feature = "zstd")),
+    allow(unused_variables)
+)]
+fn compress(data: &[u8], compression: &Compression) -> Result<Vec<u8>, String> {
+    let level = COMPRESSION_LEVEL.with(Cell::get);
+    match compression {
+        #[cfg(feature = "zlib")]
+        Compression::Zlib => {
+            use std::io::Write;
+            let mut encoder = flate2::write::ZlibEncoder::new(
+                Vec::new(),
+                flate_level(level)?,
+            );
+            encoder
+                .write_all(data)
+                .and_then(|_| encoder.finish())
+                .map_err(|e| format!("could not deflate zlib data: {}", e))
+        }
+        #[cfg(feature = "gzip")]
+        Compression::Gzip => {
+            use std::io::Write;
+            let mut encoder =
+                flate2::write::GzEncoder::new(Vec::new(), flate_level(level)?);
+            encoder
+                .write_all(data)
+                .and_then(|_| encoder.finish())
+                .map_err(|e| format!("could not deflate gzip data: {}", e))
+        }
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => {
+            // zstd takes 0 as its default level
+            let level = if level == -1 { 0 } else { level };
+            zstd::stream::encode_all(data, level)
+                .map_err(|e| format!("could not compress zstd data: {}", e))
+        }
+        #[allow(unreachable_patterns)]
+        other => Err(format!(
+            "{} compressed data requires the `{}` feature",
+            other.name(),
+            other.name()
+        )),
+    }
+}
+
+#[cfg(any(feature = "zlib", feature = "gzip"))]
+fn flate_level(level: i32) -> Result<flate2::Compression, String> {
+    match level {
+        -1 => Ok(flate2::Compression::default()),
+        0..=9 => Ok(flate2::Compression::new(level as u32)),
+        _ => Err(format!(
+            "compression level {} is not between -1 and 9",
+            level
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn hex_check() {
+        use crate::writers::write_hex;
+        assert_eq!(write_hex(86).as_deref(), Some("56"));
+        assert_eq!(write_hex(10).as_deref(), Some("0a"));
+        assert_eq!(write_hex(255).as_deref(), Some("ff"));
+        assert_eq!(write_hex(256), None);
+    }
+
+    #[test]
+    fn data_check() {
+        use crate::parsers::decode_data;
+        use crate::writers::encode_data;
+        use crate::Gid;
+
+        let gids = [Gid(1), Gid(0x8000_0002)];
+        let encoded = encode_data(&gids, None).unwrap();
+        assert_eq!(encoded, "AQAAAAIAAIA=");
+        assert_eq!(decode_data(&encoded, None).unwrap(), gids);
+    }
+}
@@ -0,0 +1,628 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Tiled's XML formats (TMX maps, TSX tilesets and TX templates) are read by
+//! rewriting the document as the JSON Tiled would have exported for it. The
+//! result goes through the same deserializers as a JSON file, so both give
+//! the same types, and errors are located by the equivalent JSON path.
+//!
+//! Nothing is validated here. A value which can't be converted is passed on
+//! as a string for the deserializer to report.
+
+use roxmltree::{Document, Node, TextPos};
+use serde_json::{Map as JsonMap, Number, Value};
+use std::fmt;
+
+/// How deeply elements may be nested. The parser recurses for each level,
+/// so without a limit a document could overflow the stack. serde_json has
+/// the same limit for JSON.
+const MAX_DEPTH: usize = 128;
+
+/// Attributes holding numbers. All others are strings, bar `BOOLS`.
+const NUMBERS: &[&str] = &[
+    "columns",
+    "compressionlevel",
+    "duration",
+    "firstgid",
+    "gid",
+    "height",
+    "hexsidelength",
+    "id",
+    "imageheight",
+    "imagewidth",
+    "margin",
+    "nextlayerid",
+    "nextobjectid",
+    "offsetx",
+    "offsety",
+    "opacity",
+    "parallaxoriginx",
+    "parallaxoriginy",
+    "parallaxx",
+    "parallaxy",
+    "pixelsize",
+    "probability",
+    "rotation",
+    "spacing",
+    "tile",
+    "tilecount",
+    "tileheight",
+    "tileid",
+    "tilewidth",
+    "width",
+    "x",
+    "y",
+];
+
+/// Attributes holding booleans, written by Tiled as 0 or 1
+const BOOLS: &[&str] = &[
+    "bold",
+    "dflip",
+    "hflip",
+    "infinite",
+    "italic",
+    "kerning",
+    "locked",
+    "repeatx",
+    "repeaty",
+    "strikeout",
+    "underline",
+    "vflip",
+    "visible",
+    "wrap",
+];
+
+/// Why a document couldn't be converted
+#[derive(Debug)]
+pub(crate) enum XmlError {
+    Parse(roxmltree::Error),
+    /// Elements nested more than `MAX_DEPTH` deep, at the first too deep
+    TooDeep(TextPos),
+}
+
+impl XmlError {
+    pub(crate) fn pos(&self) -> TextPos {
+        match self {
+            XmlError::Parse(e) => e.pos(),
+            XmlError::TooDeep(position) => *position,
+        }
+    }
+}
+
+impl fmt::Display for XmlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            XmlError::Parse(e) => e.fmt(f),
+            XmlError::TooDeep(_) => {
+                write!(f, "elements are nested more than {} deep", MAX_DEPTH)
+            }
+        }
+    }
+}
+
+/// Converts a TMX, TSX or TX document to its JSON equivalent
+pub(crate) fn to_json(text: &str) -> Result<Value, XmlError> {
+    if let Some(start) = too_deep(text) {
+        return Err(XmlError::TooDeep(text_pos(text, start)));
+    }
+    let document = Document::parse(text).map_err(XmlError::Parse)?;
+    let root = document.root_element();
+    Ok(match root.tag_name().name() {
+        "map" => map(root),
+        "tileset" => tile_set(root),
+        "template" => template(root),
+        _ => Value::Object(attributes(root)),
+    })
+}
+
+/// Where the first element nested more than `MAX_DEPTH` deep starts, if
+/// one is. Only tags are looked for, and anything else is left for the
+/// parser to check. Tags within a declaration, such as an entity's value,
+/// are counted too.
+fn too_deep(text: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut depth = 0usize;
+    let mut i = 0;
+    while let Some(offset) = bytes[i..].iter().position(|&b| b == b'<') {
+        let start = i + offset;
+        let rest = &text[start..];
+        let skip_to = |end: &str| rest.find(end).map(|j| start + j + end.len());
+        i = if rest.starts_with("<!--") {
+            skip_to("-->")?
+        } else if rest.starts_with("<![CDATA[") {
+            skip_to("]]>")?
+        } else if rest.starts_with("<?") {
+            skip_to("?>")?
+        } else if rest.starts_with("<!") {
+            start + 2
+        } else {
+            let end = start + tag_len(&bytes[start..])?;
+            if rest.starts_with("</") {
+                depth = depth.saturating_sub(1);
+            } else if bytes[end - 2] != b'/' {
+                depth += 1;
+                if depth > MAX_DEPTH {
+                    return Some(start);
+                }
+            }
+            end
+        };
+    }
+    None
+}
+
+/// The length of the tag at the start of `bytes`, up to and including its
+/// `>`, which may not be in a quoted attribute value
+fn tag_len(bytes: &[u8]) -> Option<usize> {
+    let mut quote = None;
+    for (i, &b) in bytes.iter().enumerate() {
+        match (quote, b) {
+            (None, b'>') => return Some(i + 1),
+            (None, b'"') | (None, b'\'') => quote = Some(b),
+            (Some(q), b) if q == b => quote = None,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The line and column of a byte offset, as the parser reports them
+fn text_pos(text: &str, offset: usize) -> TextPos {
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    let row = before.matches('\n').count() + 1;
+    let col = before[line_start..].chars().count() + 1;
+    TextPos::new(row as u32, col as u32)
+}
+
+fn elements<'a, 'input>(
+    node: Node<'a, 'input>,
+) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children().filter(Node::is_element)
+}
+
+fn attributes(node: Node) -> JsonMap<String, Value> {
+    node.attributes()
+        .map(|a| (a.name().to_string(), attribute(a.name(), a.value())))
+        .collect()
+}
+
+fn attribute(name: &str, value: &str) -> Value {
+    if NUMBERS.contains(&name) {
+        number(value)
+    } else if BOOLS.contains(&name) {
+        boolean(value)
+    } else {
+        Value::from(value)
+    }
+}
+
+fn number(value: &str) -> Value {
+    if let Ok(n) = value.parse::<i64>() {
+        Value::from(n)
+    } else if let Ok(n) = value.parse::<u64>() {
+        Value::from(n)
+    } else {
+        value
+            .parse::<f64>()
+            .ok()
+            .and_then(Number::from_f64)
+            .map_or_else(|| Value::from(value), Value::Number)
+    }
+}
+
+fn boolean(value: &str) -> Value {
+    match value {
+        "1" | "true" => Value::Bool(true),
+        "0" | "false" => Value::Bool(false),
+        _ => Value::from(value),
+    }
+}
+
+/// Inserts `value` unless the XML gave the field, for the fields which
+/// TMX leaves out at their default but JSON always has
+fn default(json: &mut JsonMap<String, Value>, key: &str, value: Value) {
+    json.entry(key).or_insert(value);
+}
+
+fn map(node: Node) -> Value {
+    let mut json = attributes(node);
+    let mut layers = Vec::new();
+    let mut tile_sets = Vec::new();
+    for child in elements(node) {
+        match child.tag_name().name() {
+            "properties" => {
+                json.insert("properties".into(), properties(child));
+            }
+            "tileset" => tile_sets.push(tile_set(child)),
+            "editorsettings" => {
+                let settings = elements(child)
+                    .map(|setting| {
+                        let name = setting.tag_name().name().to_string();
+                        (name, Value::Object(attributes(setting)))
+                    })
+                    .collect();
+                json.insert("editorsettings".into(), Value::Object(settings));
+            }
+            _ => layers.extend(layer(child)),
+        }
+    }
+    json.insert("layers".into(), Value::Array(layers));
+    json.insert("tilesets".into(), Value::Array(tile_sets));
+    Value::Object(json)
+}
+
+/// Any of the four layer types, or `None` if `node` isn't a layer
+fn layer(node: Node) -> Option<Value> {
+    let kind = match node.tag_name().name() {
+        "layer" => "tilelayer",
+        "objectgroup" => "objectgroup",
+        "imagelayer" => "imagelayer",
+        "group" => "group",
+        _ => return None,
+    };
+    let mut json = attributes(node);
+    json.insert("type".into(), Value::from(kind));
+    default(&mut json, "name", Value::from(""));
+    default(&mut json, "opacity", Value::from(1));
+    default(&mut json, "visible", Value::Bool(true));
+
+    let mut objects = Vec::new();
+    let mut layers = Vec::new();
+    for child in elements(node) {
+        match child.tag_name().name() {
+            "properties" => {
+                json.insert("properties".into(), properties(child));
+            }
+            "data" => data(child, &mut json),
+            "object" => objects.push(object(child)),
+            "image" => image(child, &mut json),
+            _ => layers.extend(layer(child)),
+        }
+    }
+    match kind {
+        "objectgroup" => {
+            json.insert("objects".into(), Value::Array(objects));
+        }
+        "group" => {
+            json.insert("layers".into(), Value::Array(layers));
+        }
+        _ => {}
+    }
+    Some(Value::Object(json))
+}
+
+/// The `<data>` of a tile layer, which becomes `data` or `chunks` along
+/// with `encoding` and `compression`
+fn data(node: Node, json: &mut JsonMap<String, Value>) {
+    let encoding = node.attribute("encoding");
+    if let Some(encoding) = encoding {
+        json.insert("encoding".into(), Value::from(encoding));
+    }
+    if let Some(compression) = node.attribute("compression") {
+        json.insert("compression".into(), Value::from(compression));
+    }
+
+    let chunks: Vec<Value> = elements(node)
+        .filter(|child| child.has_tag_name("chunk"))
+        .map(|chunk| {
+            let mut json = attributes(chunk);
+            json.insert("data".into(), tile_data(chunk, encoding));
+            Value::Object(json)
+        })
+        .collect();
+    if chunks.is_empty() {
+        json.insert("data".into(), tile_data(node, encoding));
+    } else {
+        json.insert("chunks".into(), Value::Array(chunks));
+    }
+}
+
+fn tile_data(node: Node, encoding: Option<&str>) -> Value {
+    match encoding {
+        Some("csv") => {
+            let text = node.text().unwrap_or("").trim();
+            if text.is_empty() {
+                return Value::Array(Vec::new());
+            }
+            Value::Array(
+                text.split(',').map(|gid| number(gid.trim())).collect(),
+            )
+        }
+        // Base64, or an unknown encoding which the deserializer rejects
+        Some(_) => Value::from(node.text().unwrap_or("").trim()),
+        // Without an encoding each tile is an element of its own
+        None => Value::Array(
+            elements(node)
+                .filter(|child| child.has_tag_name("tile"))
+                .map(|tile| {
+                    tile.attribute("gid").map_or(Value::from(0), number)
+                })
+                .collect(),
+        ),
+    }
+}
+
+/// An `<image>`, whose attributes are stored on its parent in JSON
+fn image(node: Node, json: &mut JsonMap<String, Value>) {
+    if let Some(source) = node.attribute("source") {
+        json.insert("image".into(), Value::from(source));
+    }
+    if let Some(width) = node.attribute("width") {
+        json.insert("imagewidth".into(), number(width));
+    }
+    if let Some(height) = node.attribute("height") {
+        json.insert("imageheight".into(), number(height));
+    }
+    // TMX leaves the '#' off this one colour
+    if let Some(trans) = node.attribute("trans") {
+        let trans = if trans.starts_with('#') {
+            trans.to_string()
+        } else {
+            format!("#{}", trans)
+        };
+        json.insert("transparentcolor".into(), Value::from(trans));
+    }
+}
+
+fn object(node: Node) -> Value {
+    let mut json = attributes(node);
+    // The fields of a template instance which are left out come from the
+    // template, so they mustn't be filled with defaults
+    if !json.contains_key("template") {
+        default(&mut json, "name", Value::from(""));
+        default(&mut json, "type", Value::from(""));
+        default(&mut json, "rotation", Value::from(0));
+        default(&mut json, "width", Value::from(0));
+        default(&mut json, "height", Value::from(0));
+        default(&mut json, "visible", Value::Bool(true));
+    }
+    for child in elements(node) {
+        let name = child.tag_name().name();
+        match name {
+            "properties" => {
+                json.insert("properties".into(), properties(child));
+            }
+            "ellipse" | "point" => {
+                json.insert(name.into(), Value::Bool(true));
+            }
+            "polygon" | "polyline" => {
+                let points = child.attribute("points").unwrap_or("");
+                json.insert(name.into(), self::points(points));
+            }
+            "text" => {
+                let mut text = attributes(child);
+                let content = child.text().unwrap_or("");
+                text.insert("text".into(), Value::from(content));
+                json.insert("text".into(), Value::Object(text));
+            }
+            _ => {}
+        }
+    }
+    Value::Object(json)
+}
+
+/// Points given as `x,y x,y ...`
+fn points(points: &str) -> Value {
+    Value::Array(
+        points
+            .split_whitespace()
+            .map(|point| match point.split_once(',') {
+                Some((x, y)) => {
+                    let mut json = JsonMap::new();
+                    json.insert("x".into(), number(x));
+                    json.insert("y".into(), number(y));
+                    Value::Object(json)
+                }
+                None => Value::from(point),
+            })
+            .collect(),
+    )
+}
+
+fn properties(node: Node) -> Value {
+    Value::Array(
+        elements(node)
+            .filter(|child| child.has_tag_name("property"))
+            .map(|property| {
+                let kind = property.attribute("type").unwrap_or("string");
+                let mut json = JsonMap::new();
+                if let Some(name) = property.attribute("name") {
+                    json.insert("name".into(), Value::from(name));
+                }
+                json.insert("type".into(), Value::from(kind));
+                if let Some(class) = property.attribute("propertytype") {
+                    json.insert("propertytype".into(), Value::from(class));
+                }
+                json.insert("value".into(), property_value(property, kind));
+                Value::Object(json)
+            })
+            .collect(),
+    )
+}
+
+fn property_value(node: Node, kind: &str) -> Value {
+    if kind == "class" {
+        // The members of a class are nested properties, which JSON keeps
+        // as an object of plain values
+        let mut members = JsonMap::new();
+        for list in elements(node).filter(|c| c.has_tag_name("properties")) {
+            for member in elements(list).filter(|c| c.has_tag_name("property"))
+            {
+                let kind = member.attribute("type").unwrap_or("string");
+                let name = member.attribute("name").unwrap_or("");
+                members.insert(name.into(), property_value(member, kind));
+            }
+        }
+        return Value::Object(members);
+    }
+
+    // Multi-line strings are stored as the element's text
+    let value = node
+        .attribute("value")
+        .or_else(|| node.text())
+        .unwrap_or("");
+    match kind {
+        "int" | "float" | "object" => number(value),
+        "bool" => boolean(value),
+        _ => Value::from(value),
+    }
+}
+
+fn tile_set(node: Node) -> Value {
+    let mut json = attributes(node);
+    // A reference to a TSX file
+    if json.contains_key("source") {
+        return Value::Object(json);
+    }
+
+    let mut tiles = Vec::new();
+    for child in elements(node) {
+        match child.tag_name().name() {
+            "properties" => {
+                json.insert("properties".into(), properties(child));
+            }
+            "image" => image(child, &mut json),
+            "tileoffset" => {
+                json.insert(
+                    "tileoffset".into(),
+                    Value::Object(attributes(child)),
+                );
+            }
+            "tile" => tiles.push(tile(child)),
+            "terraintypes" => {
+                let terrains = elements(child)
+                    .filter(|c| c.has_tag_name("terrain"))
+                    .map(|terrain| {
+                        let mut json = attributes(terrain);
+                        for list in elements(terrain) {
+                            if list.has_tag_name("properties") {
+                                json.insert(
+                                    "properties".into(),
+                                    properties(list),
+                                );
+                            }
+                        }
+                        Value::Object(json)
+                    })
+                    .collect();
+                json.insert("terrains".into(), Value::Array(terrains));
+            }
+            "wangsets" => {
+                let wang_sets = elements(child)
+                    .filter(|c| c.has_tag_name("wangset"))
+                    .map(wang_set)
+                    .collect();
+                json.insert("wangsets".into(), Value::Array(wang_sets));
+            }
+            _ => {}
+        }
+    }
+    if !tiles.is_empty() {
+        json.insert("tiles".into(), Value::Array(tiles));
+    }
+    Value::Object(json)
+}
+
+fn tile(node: Node) -> Value {
+    let mut json = attributes(node);
+    // Corner terrains, eg; "0,0,,1", where an empty corner is -1 in JSON
+    if let Some(terrain) = node.attribute("terrain") {
+        let corners = terrain
+            .split(',')
+            .map(|corner| match corner.trim() {
+                "" => Value::from(-1),
+                corner => number(corner),
+            })
+            .collect();
+        json.insert("terrain".into(), Value::Array(corners));
+    }
+    for child in elements(node) {
+        match child.tag_name().name() {
+            "properties" => {
+                json.insert("properties".into(), properties(child));
+            }
+            "image" => image(child, &mut json),
+            "objectgroup" => {
+                json.insert("objectgroup".into(), layer(child).unwrap());
+            }
+            "animation" => {
+                let frames = elements(child)
+                    .filter(|c| c.has_tag_name("frame"))
+                    .map(|frame| Value::Object(attributes(frame)))
+                    .collect();
+                json.insert("animation".into(), Value::Array(frames));
+            }
+            _ => {}
+        }
+    }
+    Value::Object(json)
+}
+
+fn wang_set(node: Node) -> Value {
+    let mut json = attributes(node);
+    let mut corner_colors = Vec::new();
+    let mut edge_colors = Vec::new();
+    let mut colors = Vec::new();
+    let mut wang_tiles = Vec::new();
+    for child in elements(node) {
+        let color = || Value::Object(attributes(child));
+        match child.tag_name().name() {
+            "wangcornercolor" => corner_colors.push(color()),
+            "wangedgecolor" => edge_colors.push(color()),
+            "wangcolor" => colors.push(color()),
+            "wangtile" => {
+                let mut tile = attributes(child);
+                if let Some(wang_id) = child.attribute("wangid") {
+                    tile.insert("wangid".into(), self::wang_id(wang_id));
+                }
+                default(&mut tile, "hflip", Value::Bool(false));
+                default(&mut tile, "vflip", Value::Bool(false));
+                default(&mut tile, "dflip", Value::Bool(false));
+                wang_tiles.push(Value::Object(tile));
+            }
+            _ => {}
+        }
+    }
+    // Tiled 1.5 replaced corner and edge colours with a single list
+    if colors.is_empty() {
+        json.insert("cornercolors".into(), Value::Array(corner_colors));
+        json.insert("edgecolors".into(), Value::Array(edge_colors));
+    } else {
+        json.insert("colors".into(), Value::Array(colors));
+    }
+    json.insert("wangtiles".into(), Value::Array(wang_tiles));
+    Value::Object(json)
+}
+
+/// A Wang ID is a list of colour indexes. Before Tiled 1.5 it was written
+/// to TMX as a hex number, one digit per index with the first index lowest.
+fn wang_id(wang_id: &str) -> Value {
+    if let Some(hex) = wang_id.strip_prefix("0x") {
+        if let Ok(id) = u32::from_str_radix(hex, 16) {
+            return Value::Array(
+                (0..8).map(|i| Value::from((id >> (i * 4)) & 0xf)).collect(),
+            );
+        }
+        return Value::from(wang_id);
+    }
+    Value::Array(wang_id.split(',').map(|i| number(i.trim())).collect())
+}
+
+fn template(node: Node) -> Value {
+    let mut json = JsonMap::new();
+    json.insert("type".into(), Value::from("template"));
+    for child in elements(node) {
+        match child.tag_name().name() {
+            "tileset" => {
+                json.insert("tileset".into(), tile_set(child));
+            }
+            "object" => {
+                json.insert("object".into(), object(child));
+            }
+            _ => {}
+        }
+    }
+    Value::Object(json)
+}
@@ -0,0 +1,39 @@
+<?xml version="1.0" encoding="UTF-8"?>
+<tileset version="1.2" tiledversion="1.2.4" name="base" tilewidth="32" tileheight="32" spacing="1" margin="0" tilecount="9" columns="3">
+ <image source="numbers.png" width="100" height="100"/>
+ <tile id="0">
+  <properties>
+   <property name="number" type="int" value="1"/>
+  </properties>
+  <objectgroup draworder="index">
+   <object x="0" y="0" width="10.25" height="25.25"/>
+  </objectgroup>
+ </tile>
+ <tile id="1">
+  <properties>
+   <property name="number" type="int" value="2"/>
+  </properties>
+  <objectgroup draworder="index">
+   <object id="1" name="collision" x="0.666666666666667" y="1">
+    <polygon points="0,0 16.6666666666667,9.66666666666667 31.6666666666667,-1 31.6666666666667,30.3333333333333 0,31"/>
+   </object>
+  </objectgroup>
+ </tile>
+ <tile id="2">
+  <properties>
+   <property name="number" type="int" value="3"/>
+  </properties>
+ </tile>
+ <tile id="4" type="five"/>
+ <tile id="6">
+  <animation>
+   <frame tileid="0" duration="200"/>
+   <frame tileid="1" duration="300"/>
+   <frame tileid="2" duration="400"/>
+   <frame tileid="3" duration="500"/>
+   <frame tileid="4" duration="600"/>
+   <frame tileid="5" duration="700"/>
+   <frame tileid="6" duration="2000"/>
+  </animation>
+ </tile>
+</tileset>
@@ -0,0 +1,282 @@
+use tiled_json_rs::{Map, Vec2};
+
+fn map(orientation: &str, extra: &str, tile_width: u32) -> Map {
+    Map::load_from_str(&format!(
+        r##"
+        {{
+          "height":4,
+          "infinite":true,
+          "layers":[],
+          "orientation":"{}",
+          "tileheight":32,
+          "tilesets":[],
+          "tilewidth":{},
+          "width":4
+          {}
+        }}"##,
+        orientation, tile_width, extra
+    ))
+    .unwrap()
+}
+
+fn every_layout() -> Vec<Map> {
+    let mut maps = vec![map("orthogonal", "", 24), map("isometric", "", 64)];
+    for axis in ["x", "y"] {
+        for index in ["odd", "even"] {
+            let stagger = format!(
+                r#", "staggeraxis":"{}", "staggerindex":"{}""#,
+                axis, index
+            );
+            maps.push(map("staggered", &stagger, 64));
+            let hex = format!(r#"{}, "hexsidelength":14"#, stagger);
+            maps.push(map("hexagonal", &hex, 28));
+        }
+    }
+    maps
+}
+
+fn tile(x: i32, y: i32) -> Vec2<i32> {
+    Vec2 { x, y }
+}
+
+#[test]
+fn centres_convert_back_to_their_tile() {
+    for map in every_layout() {
+        for y in -3..6 {
+            for x in -3..6 {
+                let corner = map.tile_to_pixel(tile(x, y));
+                let centre = Vec2 {
+                    x: corner.x + map.tile_width as f32 / 2.0,
+                    y: corner.y + map.tile_height as f32 / 2.0,
+                };
+                assert_eq!(
+                    map.pixel_to_tile(centre),
+                    tile(x, y),
+                    "{:?} {:?}",
+                    map.orientation,
+                    map.stagger_axis
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn tile_positions() {
+    let ortho = map("orthogonal", "", 24);
+    assert_eq!(ortho.tile_to_pixel(tile(2, 3)), Vec2 { x: 48.0, y: 96.0 });
+    assert_eq!(ortho.pixel_to_tile(Vec2 { x: -1.0, y: 0.0 }), tile(-1, 0));
+
+    // The left corner of the map is at 0, so the first tile's top corner
+    // is half a tile width in per row of the map
+    let iso = map("isometric", "", 64);
+    assert_eq!(iso.tile_to_pixel(tile(0, 0)), Vec2 { x: 96.0, y: 0.0 });
+    assert_eq!(iso.tile_to_pixel(tile(1, 0)), Vec2 { x: 128.0, y: 16.0 });
+    // Just inside the left corner of the first tile's diamond
+    assert_eq!(iso.pixel_to_tile(Vec2 { x: 97.0, y: 16.0 }), tile(0, 0));
+    // Across its upper left edge
+    assert_eq!(iso.pixel_to_tile(Vec2 { x: 97.0, y: 15.0 }), tile(-1, 0));
+
+    let staggered = map("staggered", "", 64);
+    assert_eq!(
+        staggered.tile_to_pixel(tile(0, 1)),
+        Vec2 { x: 32.0, y: 16.0 }
+    );
+    // The top-left corner of the first tile's box is in the diamond above
+    // and to the left of it
+    assert_eq!(
+        staggered.pixel_to_tile(Vec2 { x: 1.0, y: 1.0 }),
+        tile(-1, -1)
+    );
+
+    let hex = map("hexagonal", r#", "hexsidelength":16"#, 32);
+    assert_eq!(hex.tile_to_pixel(tile(0, 1)), Vec2 { x: 16.0, y: 24.0 });
+    assert_eq!(hex.tile_to_pixel(tile(1, 2)), Vec2 { x: 32.0, y: 48.0 });
+}
+
+#[test]
+fn neighbours_are_mutual() {
+    for map in every_layout() {
+        let expected = match map.orientation {
+            tiled_json_rs::Orientation::Hexagonal => 6,
+            _ => 4,
+        };
+        for y in -2..3 {
+            for x in -2..3 {
+                let neighbours = map.neighbours(tile(x, y));
+                assert_eq!(neighbours.len(), expected);
+                for neighbour in neighbours {
+                    assert!(
+                        map.neighbours(neighbour).contains(&tile(x, y)),
+                        "{:?} {:?}",
+                        map.orientation,
+                        map.stagger_axis
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn hexagonal_neighbours() {
+    let hex = map("hexagonal", r#", "hexsidelength":16"#, 32);
+    // Odd rows are shifted right
+    assert_eq!(
+        hex.neighbours(tile(1, 1)),
+        vec![
+            tile(1, 0),
+            tile(2, 0),
+            tile(2, 1),
+            tile(2, 2),
+            tile(1, 2),
+            tile(0, 1)
+        ]
+    );
+}
+
+#[test]
+fn finite_maps_leave_out_neighbours_outside() {
+    let mut ortho = map("orthogonal", "", 24);
+    ortho.infinite = false;
+    assert_eq!(ortho.neighbours(tile(0, 0)), vec![tile(1, 0), tile(0, 1)]);
+    assert_eq!(ortho.neighbours(tile(3, 3)), vec![tile(3, 2), tile(2, 3)]);
+}
+
+#[test]
+fn tall_tiles_align_to_the_bottom_of_their_cell() {
+    let map = Map::load_from_str(
+        r##"
+        {
+          "height":2,
+          "layers":[],
+          "orientation":"orthogonal",
+          "tileheight":32,
+          "tilesets":[
+            { "columns":1, "firstgid":1, "image":"trees.png",
+              "imageheight":64, "imagewidth":32, "name":"trees",
+              "tilecount":1, "tileheight":64, "tilewidth":32,
+              "tileoffset":{ "x":2, "y":0 } }],
+          "tilewidth":32,
+          "width":2
+        }"##,
+    )
+    .unwrap();
+    assert_eq!(map.tile_position_on_map(3, 1), Vec2 { x: 34, y: 0 });
+    assert_eq!(map.tile_position_on_map(0, 1), Vec2 { x: 2, y: -32 });
+}
+
+const PICKING: &str = r##"
+    {
+      "height":2,
+      "layers":[
+        { "data":[1, 2, 3, 0], "height":2, "name":"ground", "opacity":1,
+          "type":"tilelayer", "visible":true, "width":2 },
+        { "layers":[
+            { "data":[5, 6, 7, 8], "height":2, "name":"shifted",
+              "offsetx":4, "offsety":-2, "opacity":1, "parallaxx":0.5,
+              "type":"tilelayer", "visible":true, "width":2 }],
+          "name":"group", "offsetx":16, "opacity":1, "parallaxy":0.5,
+          "type":"group", "visible":true },
+        { "chunks":[
+            { "data":[9, 10, 11, 12], "height":2, "width":2, "x":-2,
+              "y":-2 }],
+          "height":2, "name":"chunked", "opacity":1, "type":"tilelayer",
+          "visible":true, "width":2 }],
+      "orientation":"orthogonal",
+      "tileheight":32,
+      "tilesets":[],
+      "tilewidth":32,
+      "width":2
+    }"##;
+
+#[test]
+fn picking_tiles() {
+    let map = Map::load_from_str(PICKING).unwrap();
+    let ground = &map.layers[0];
+    let at = |x, y| Vec2 { x, y };
+    assert_eq!(
+        map.tile_at_pixel(at(40.0, 10.0), ground),
+        Some((tile(1, 0), 2.into()))
+    );
+    // An empty cell is still a cell of the layer
+    assert_eq!(
+        map.tile_at_pixel(at(40.0, 40.0), ground),
+        Some((tile(1, 1), 0.into()))
+    );
+    assert_eq!(map.tile_at_pixel(at(-1.0, 10.0), ground), None);
+
+    // Negative tiles of an infinite map are in its chunks
+    let chunked = &map.layers[2];
+    assert_eq!(
+        map.tile_at_pixel(at(-1.0, -1.0), chunked),
+        Some((tile(-1, -1), 12.into()))
+    );
+    assert_eq!(map.tile_at_pixel(at(1.0, 1.0), chunked), None);
+
+    // A group isn't a tile layer, nor is a layer from another map
+    assert_eq!(map.tile_at_pixel(at(1.0, 1.0), &map.layers[1]), None);
+    let copy = map.layers[0].clone();
+    assert_eq!(map.tile_at_pixel(at(1.0, 1.0), &copy), None);
+}
+
+#[test]
+fn picking_accounts_for_offsets_and_parallax() {
+    let map = Map::load_from_str(PICKING).unwrap();
+    let shifted = match &map.layers[1].layer_type {
+        tiled_json_rs::LayerType::Group { layers } => &layers[0],
+        _ => panic!("Second layer should be a group"),
+    };
+    let at = |x, y| Vec2 { x, y };
+    // Offset by 20, -2 with the group's offset
+    assert_eq!(map.tile_at_pixel(at(19.0, 0.0), shifted), None);
+    assert_eq!(
+        map.tile_at_pixel(at(20.0, 29.0), shifted),
+        Some((tile(0, 0), 5.into()))
+    );
+    assert_eq!(
+        map.tile_at_pixel(at(20.0, 30.0), shifted),
+        Some((tile(0, 1), 7.into()))
+    );
+
+    // With the view centred at 64, 64 the layer has moved by half of that
+    // horizontally from its own parallax, and vertically from its group's
+    let view = at(64.0, 64.0);
+    assert_eq!(
+        map.tile_at_pixel_in_view(at(51.0, 30.0), shifted, view),
+        None
+    );
+    assert_eq!(
+        map.tile_at_pixel_in_view(at(52.0, 30.0), shifted, view),
+        Some((tile(0, 0), 5.into()))
+    );
+    assert_eq!(
+        map.tile_at_pixel_in_view(at(52.0, 62.0), shifted, view),
+        Some((tile(0, 1), 7.into()))
+    );
+}
+
+#[test]
+fn picking_without_a_view_ignores_parallax() {
+    let map = Map::load_from_str(&PICKING.replace(
+        r#""orientation""#,
+        r#""parallaxoriginx":100, "parallaxoriginy":-60, "orientation""#,
+    ))
+    .unwrap();
+    let shifted = match &map.layers[1].layer_type {
+        tiled_json_rs::LayerType::Group { layers } => &layers[0],
+        _ => panic!("Second layer should be a group"),
+    };
+    let at = |x, y| Vec2 { x, y };
+    // Only offset, as with the view at the parallax origin
+    assert_eq!(
+        map.tile_at_pixel(at(20.0, -2.0), shifted),
+        Some((tile(0, 0), 5.into()))
+    );
+    assert_eq!(map.tile_at_pixel(at(19.0, -2.0), shifted), None);
+    let origin = at(100.0, -60.0);
+    assert_eq!(
+        map.tile_at_pixel_in_view(at(20.0, -2.0), shifted, origin),
+        Some((tile(0, 0), 5.into()))
+    );
+}
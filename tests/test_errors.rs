@@ -0,0 +1,282 @@
+use std::path::Path;
+use tiled_json_rs::{Error, Map};
+
+fn map_with(layers: &str, tile_sets: &str) -> String {
+    format!(
+        r##"
+        {{
+          "height":2,
+          "layers":[{}],
+          "orientation":"orthogonal",
+          "tileheight":32,
+          "tilesets":[{}],
+          "tilewidth":32,
+          "width":2
+        }}"##,
+        layers, tile_sets
+    )
+}
+
+#[test]
+fn io_error_names_file() {
+    let p = Path::new("tests/data/does_not_exist.json");
+    let err = Map::load_from_file(p).expect_err("should fail");
+    assert!(matches!(err, Error::Io { .. }));
+    assert_eq!(err.file(), Some(p));
+    assert_eq!(err.json_path(), None);
+}
+
+#[test]
+fn syntax_error_has_position() {
+    let err = Map::load_from_str("{\n  \"height\": }").err().unwrap();
+    match err {
+        Error::Syntax {
+            file, line, column, ..
+        } => {
+            assert!(file.is_none());
+            assert_eq!((line, column), (2, 13));
+        }
+        e => panic!("expected a syntax error, got {:?}", e),
+    }
+}
+
+#[test]
+fn schema_error_has_json_path() {
+    let layers = r##"
+        {
+          "name":"objects",
+          "objects":[
+            {
+              "height":"tall",
+              "id":1,
+              "name":"",
+              "rotation":0,
+              "width":0
+            }],
+          "opacity":1,
+          "type":"objectgroup",
+          "visible":true
+        }"##;
+    let err = Map::load_from_str(&map_with(layers, "")).err().unwrap();
+    assert!(matches!(err, Error::Schema { .. }), "{:?}", err);
+    assert_eq!(err.json_path(), Some("layers[0].objects[0].height"));
+}
+
+#[test]
+fn decode_error_has_json_path() {
+    let layers = r##"
+        {
+          "data":"not base64!",
+          "encoding":"base64",
+          "height":2,
+          "name":"ground",
+          "opacity":1,
+          "type":"tilelayer",
+          "visible":true,
+          "width":2
+        }"##;
+    let err = Map::load_from_str(&map_with(layers, "")).err().unwrap();
+    assert!(matches!(err, Error::Decode { .. }), "{:?}", err);
+    assert_eq!(err.json_path(), Some("layers[0].data"));
+}
+
+#[test]
+fn decompress_error_has_json_path() {
+    let layers = r##"
+        {
+          "chunks":[
+            {
+              "data":"AAAAAA==",
+              "height":2,
+              "width":2,
+              "x":0,
+              "y":0
+            }],
+          "compression":"zlib",
+          "encoding":"base64",
+          "height":2,
+          "name":"ground",
+          "opacity":1,
+          "type":"tilelayer",
+          "visible":true,
+          "width":2
+        }"##;
+    let err = Map::load_from_str(&map_with(layers, "")).err().unwrap();
+    assert!(matches!(err, Error::Decompress { .. }), "{:?}", err);
+    assert_eq!(err.json_path(), Some("layers[0].chunks[0].data"));
+}
+
+#[test]
+fn missing_tileset_is_a_missing_reference() {
+    let tile_sets = r##"{ "firstgid":1, "source":"missing.json" }"##;
+    let mut map = Map::load_from_str(&map_with("", tile_sets)).unwrap();
+    let err = map
+        .resolve_tile_sets(Path::new("tests/data"))
+        .err()
+        .unwrap();
+    match &err {
+        Error::MissingReference { reference, .. } => {
+            assert_eq!(reference, Path::new("tests/data/missing.json"));
+        }
+        e => panic!("expected a missing reference, got {:?}", e),
+    }
+    assert_eq!(err.json_path(), Some("tilesets[0]"));
+}
+
+#[test]
+fn missing_template_is_a_missing_reference() {
+    let layers = r##"
+        {
+          "name":"objects",
+          "objects":[
+            { "id":1, "template":"templates/missing.json", "x":0, "y":0 }],
+          "opacity":1,
+          "type":"objectgroup",
+          "visible":true
+        }"##;
+    let dir = std::env::temp_dir()
+        .join(format!("tiled-json-rs-template-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("map.json");
+    std::fs::write(&file, map_with(layers, "")).unwrap();
+    let err = Map::load_from_file(&file).expect_err("should fail");
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    match &err {
+        Error::MissingReference { reference, .. } => {
+            assert_eq!(reference, &dir.join("templates/missing.json"));
+        }
+        e => panic!("expected a missing reference, got {:?}", e),
+    }
+    assert_eq!(err.file(), Some(file.as_path()));
+    assert_eq!(err.json_path(), Some("layers[0].objects[0].template"));
+    assert!(
+        err.to_string().contains("templates/missing.json"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn template_tileset_must_be_in_the_map() {
+    let layers = r##"
+        {
+          "name":"objects",
+          "objects":[
+            { "id":1, "template":"tile.json", "x":0, "y":0 }],
+          "opacity":1,
+          "type":"objectgroup",
+          "visible":true
+        }"##;
+    let dir = std::env::temp_dir()
+        .join(format!("tiled-json-rs-tileset-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let load = |template_tile_set: &str, tile_sets: &str| {
+        std::fs::write(
+            dir.join("tile.json"),
+            format!(
+                r##"{{ "object":{{ "gid":2, "name":"", "rotation":0,
+                                   "height":32, "width":32 }},
+                      "tileset":{},
+                      "type":"template" }}"##,
+                template_tile_set
+            ),
+        )
+        .unwrap();
+        let file = dir.join("map.json");
+        std::fs::write(&file, map_with(layers, tile_sets)).unwrap();
+        Map::load_from_file_unresolved(&file).expect_err("should fail")
+    };
+    let items = r#"{ "firstgid":1, "source":"items.json" }"#;
+
+    let cases = [
+        // Not one of the map's
+        (
+            items,
+            r#"{ "firstgid":1, "source":"terrain.json" }"#,
+            "map.json",
+            "layers[0].objects[0].template",
+        ),
+        // The tile would be past the last GID
+        (
+            items,
+            r#"{ "firstgid":4294967295, "source":"items.json" }"#,
+            "map.json",
+            "layers[0].objects[0].template",
+        ),
+        // Neither first GID fits in a GID
+        (
+            items,
+            r#"{ "firstgid":4294967296, "source":"items.json" }"#,
+            "map.json",
+            "tilesets[0].firstgid",
+        ),
+        (
+            r#"{ "firstgid":4294967296, "source":"items.json" }"#,
+            items,
+            "tile.json",
+            "tileset.firstgid",
+        ),
+    ];
+    let errors: Vec<_> = cases
+        .iter()
+        .map(|(template, tile_sets, _, _)| load(template, tile_sets))
+        .collect();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    for (err, (_, _, file, json_path)) in errors.iter().zip(&cases) {
+        assert!(matches!(err, Error::Schema { .. }), "{:?}", err);
+        assert_eq!(err.file(), Some(dir.join(file).as_path()));
+        assert_eq!(err.json_path(), Some(*json_path));
+    }
+}
+
+#[cfg(not(feature = "xml"))]
+#[test]
+fn tmx_needs_xml_feature() {
+    let p = Path::new("tests/data/csv.tmx");
+    let err = Map::load_from_file(p).expect_err("should fail");
+    assert!(matches!(err, Error::Syntax { .. }));
+    assert!(err.to_string().contains("`xml` feature"), "{}", err);
+}
+
+#[test]
+fn malformed_shapes_are_errors() {
+    let object = |shape: &str| {
+        format!(
+            r##"
+            {{
+              "name":"objects",
+              "objects":[
+                {{
+                  "height":0,
+                  "id":1,
+                  "name":"",
+                  "rotation":0,
+                  "width":0,
+                  {}
+                }}],
+              "opacity":1,
+              "type":"objectgroup",
+              "visible":true
+            }}"##,
+            shape
+        )
+    };
+    let cases = [
+        (r#""polygon":[{ "x":0, "y":"up" }]"#, ".polygon[0].y"),
+        (r#""polyline":{ "x":0, "y":0 }"#, ".polyline"),
+        (r#""ellipse":"yes""#, ".ellipse"),
+        (r#""text":{ "wrap":true }"#, ".text"),
+        (r#""text":{ "text":"", "valign":"middle" }"#, ".text.valign"),
+        // Only one shape may be given
+        (r#""ellipse":true, "polygon":[]"#, ""),
+    ];
+    for (shape, path) in cases {
+        let map = map_with(&object(shape), "");
+        let err = Map::load_from_str(&map).err().unwrap();
+        assert!(matches!(err, Error::Schema { .. }), "{:?}", err);
+        let expected = format!("layers[0].objects[0]{}", path);
+        assert_eq!(err.json_path(), Some(expected.as_str()), "{}", shape);
+    }
+}
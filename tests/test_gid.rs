@@ -0,0 +1,128 @@
+use tiled_json_rs::{Gid, LayerType, Map};
+
+const MAP: &str = r##"
+    {
+      "height":1,
+      "layers":[
+        {
+          "data":[2, 2147483650, 1073741826, 536870914, 3758096386, 0],
+          "height":1,
+          "name":"ground",
+          "opacity":1,
+          "type":"tilelayer",
+          "visible":true,
+          "width":6,
+          "x":0,
+          "y":0
+        },
+        {
+          "draworder":"topdown",
+          "name":"objects",
+          "objects":[
+            {
+              "gid":2147483651,
+              "height":32,
+              "id":1,
+              "name":"",
+              "rotation":0,
+              "type":"",
+              "visible":true,
+              "width":32,
+              "x":0,
+              "y":32
+            }],
+          "opacity":1,
+          "type":"objectgroup",
+          "visible":true,
+          "x":0,
+          "y":0
+        }],
+      "orientation":"orthogonal",
+      "tileheight":32,
+      "tilesets":[
+        {
+          "columns":2,
+          "firstgid":1,
+          "image":"tiles.png",
+          "imageheight":64,
+          "imagewidth":64,
+          "margin":0,
+          "name":"tiles",
+          "spacing":0,
+          "tilecount":4,
+          "tileheight":32,
+          "tilewidth":32
+        }],
+      "tilewidth":32,
+      "width":6
+    }"##;
+
+#[test]
+fn gid_flags() {
+    let gid = Gid(0xE000_0002);
+    assert_eq!(gid.id(), 2);
+    assert_eq!(gid.raw(), 0xE000_0002);
+    assert!(gid.flip_h() && gid.flip_v() && gid.flip_d());
+    assert!(!gid.rotate_hex120());
+
+    let gid = Gid(0x1000_0000);
+    assert!(gid.rotate_hex120());
+    assert!(gid.is_empty());
+
+    let gid = Gid::from(3);
+    assert!(!gid.flip_h() && !gid.flip_v() && !gid.flip_d());
+    assert_eq!(gid, 3);
+}
+
+#[test]
+fn layer_data_keeps_flags() {
+    let map = Map::load_from_str(MAP).unwrap();
+    let data = match &map.layers[0].layer_type {
+        LayerType::TileLayer(tiles) => &tiles.data,
+        _ => panic!("First layer should be a tile layer"),
+    };
+    assert!(data.iter().take(5).all(|gid| gid.id() == 2));
+    assert!(data[1].flip_h() && !data[1].flip_v());
+    assert!(data[2].flip_v() && !data[2].flip_h());
+    assert!(data[3].flip_d());
+    assert!(data[5].is_empty());
+
+    let object = match &map.layers[1].layer_type {
+        LayerType::ObjectGroup(group) => &group.objects[0],
+        _ => panic!("Second layer should be an object group"),
+    };
+    let gid = object.gid.unwrap();
+    assert_eq!(gid.id(), 3);
+    assert!(gid.flip_h());
+}
+
+#[test]
+fn lookups_ignore_flags() {
+    let map = Map::load_from_str(MAP).unwrap();
+    let plain = map.tile_position_on_image(2);
+    for raw in [0x8000_0002, 0x4000_0002, 0x2000_0002, 0xE000_0002] {
+        assert_eq!(map.tileset_name(raw), Some("tiles"));
+        assert_eq!(
+            map.tileset_image_path(Gid(raw)).unwrap().to_str(),
+            Some("tiles.png")
+        );
+        assert_eq!(map.tile_position_on_image(raw), plain);
+    }
+    assert_eq!(plain.unwrap().x, 32);
+
+    let tile_set = map.tile_sets[0].internal();
+    assert!(tile_set.has_tile(0x8000_0004));
+    assert!(!tile_set.has_tile(0x8000_0005));
+    assert_eq!(tile_set.lid(0x4000_0004), Some(3));
+}
+
+#[test]
+fn empty_gid_has_no_tile() {
+    let map = Map::load_from_str(MAP).unwrap();
+    let tile_set = map.tile_sets[0].internal();
+    assert_eq!(tile_set.lid(0u32), None);
+    assert!(tile_set.get_tile(0u32).is_none());
+    assert!(!tile_set.has_tile(0u32));
+    assert!(map.tile_info(0u32).is_none());
+    assert!(map.tile_image_path(Gid(0)).is_none());
+}
@@ -1,4 +1,7 @@
-use tiled_json_rs::{Chunk, Color, DrawOrder, Layer, LayerType, TiledValue};
+use tiled_json_rs::{
+    Chunk, ChunkSize, Color, DrawOrder, Gid, Layer, LayerType, Map, Object,
+    Rect, TileLayer, TileRect, TiledValue, Vec2,
+};
 
 #[test]
 fn parse_tile_layer_no_encoding() {
@@ -29,12 +32,9 @@ fn parse_tile_layer_no_encoding() {
 
     let m: Layer = serde_json::from_str(data).expect("fail");
 
-    match m.layer_type {
-        LayerType::TileLayer(data) => {
-            assert!(data.chunks.is_none());
-            assert_eq!(data.data.len(), 16);
-        }
-        _ => {}
+    if let LayerType::TileLayer(data) = m.layer_type {
+        assert!(data.chunks.is_none());
+        assert_eq!(data.data.len(), 16);
     }
 
     assert_eq!(m.properties["test"], TiledValue::Bool(false));
@@ -62,11 +62,8 @@ fn parse_tile_layer_encoding() {
 
     let m: Layer = serde_json::from_str(data).expect("fail");
 
-    match m.layer_type {
-        LayerType::TileLayer(data) => {
-            assert_eq!(data.data.len(), 11);
-        }
-        _ => {}
+    if let LayerType::TileLayer(data) = m.layer_type {
+        assert_eq!(data.data.len(), 11);
     }
 }
 
@@ -115,11 +112,8 @@ fn check_property_variants() {
 
     let m: Layer = serde_json::from_str(data).expect("fail");
 
-    match m.layer_type {
-        LayerType::ObjectGroup(data) => {
-            assert_eq!(&data.draw_order, &DrawOrder::TopDown);
-        }
-        _ => {}
+    if let LayerType::ObjectGroup(data) = m.layer_type {
+        assert_eq!(&data.draw_order, &DrawOrder::TopDown);
     }
 
     assert_eq!(m.properties["layerProp1"], TiledValue::Bool(true));
@@ -127,9 +121,9 @@ fn check_property_variants() {
     assert_eq!(m.properties["layerProp3"], TiledValue::Int(1));
     match &m.properties["layerProp4"] {
         TiledValue::Color(c) => {
-            assert_eq!(c.r(), 47);
-            assert_eq!(c.g(), 162);
-            assert_eq!(c.b(), 201);
+            assert_eq!(c.r(), 242);
+            assert_eq!(c.g(), 42);
+            assert_eq!(c.b(), 156);
             assert_eq!(c.a(), 255);
         }
         _ => panic!("shouldn't be a color"),
@@ -219,3 +213,489 @@ fn chunk() {
     assert_eq!(m.data[2], 1);
     assert_eq!(m.y, -16);
 }
+
+#[cfg(feature = "zlib")]
+#[test]
+fn parse_tile_layer_compressed_chunks() {
+    use tiled_json_rs::Compression;
+
+    // Two zlib compressed chunks of 2x2 tiles: [1, 2, 3, 4] and [0, 0, 0, 5]
+    let data = r##"
+        {
+          "chunks":[
+            {
+              "data":"eJxjZGBgYAJiZiBmAWIAAGAACw==",
+              "height":2,
+              "width":2,
+              "x":0,
+              "y":0
+            },
+            {
+              "data":"eJxjYEAAViAGAAAkAAY=",
+              "height":2,
+              "width":2,
+              "x":2,
+              "y":-2
+            }],
+          "compression":"zlib",
+          "encoding":"base64",
+          "height":4,
+          "name":"ground",
+          "opacity":1,
+          "type":"tilelayer",
+          "visible":true,
+          "width":4,
+          "x":0,
+          "y":0
+        }"##;
+
+    let m: Layer = serde_json::from_str(data).expect("fail");
+
+    if let LayerType::TileLayer(tiles) = m.layer_type {
+        let chunks = tiles.chunks.expect("chunks");
+        assert_eq!(chunks[0].data, vec![1, 2, 3, 4]);
+        assert_eq!(chunks[1].data, vec![0, 0, 0, 5]);
+        assert_eq!(tiles.compression, Some(Compression::Zlib));
+    } else {
+        panic!("Should have been a LayerType::TileLayer");
+    }
+}
+
+fn infinite_layer() -> TileLayer {
+    serde_json::from_str(
+        r##"
+        {
+          "chunks":[
+            { "data":[1, 0, 0, 2], "height":2, "width":2, "x":-2, "y":-2 },
+            { "data":[3, 0, 0, 0], "height":2, "width":2, "x":2, "y":0 }],
+          "height":4,
+          "width":6
+        }"##,
+    )
+    .unwrap()
+}
+
+#[test]
+fn infinite_layer_cells() {
+    let layer = infinite_layer();
+    assert_eq!(layer.get(-2, -2), Some(Gid(1)));
+    assert_eq!(layer.get(-1, -1), Some(Gid(2)));
+    assert_eq!(layer.get(-1, -2), Some(Gid(0)));
+    assert_eq!(layer.get(2, 0), Some(Gid(3)));
+    // Between the chunks
+    assert_eq!(layer.get(0, 0), None);
+
+    let cells: Vec<_> = layer.iter_cells().collect();
+    assert_eq!(
+        cells,
+        vec![(-2, -2, Gid(1)), (-1, -1, Gid(2)), (2, 0, Gid(3))]
+    );
+    assert_eq!(
+        layer.bounds(),
+        TileRect {
+            x: -2,
+            y: -2,
+            width: 6,
+            height: 4
+        }
+    );
+}
+
+#[test]
+fn densified_layers_match() {
+    let layer = infinite_layer();
+    let dense = layer.densify();
+    assert!(dense.chunks.is_none());
+    assert_eq!((dense.width, dense.height), (6, 4));
+    assert_eq!(dense.get(0, 0), Some(Gid(1)));
+    assert_eq!(dense.get(4, 2), Some(Gid(3)));
+    // The gaps between chunks become empty tiles
+    assert_eq!(dense.get(2, 2), Some(Gid(0)));
+
+    let bounds = layer.bounds();
+    let moved: Vec<_> = dense
+        .iter_cells()
+        .map(|(x, y, gid)| (x + bounds.x, y + bounds.y, gid))
+        .collect();
+    assert_eq!(moved, layer.iter_cells().collect::<Vec<_>>());
+
+    // A finite layer is already dense
+    assert_eq!(dense.densify(), dense);
+    assert_eq!(dense.bounds().width, 6);
+}
+
+fn grid() -> TileLayer {
+    serde_json::from_str(
+        r##"{ "data":[1, 2, 3, 4, 0, 6, 7, 8, 9, 10, 11, 0], "height":3,
+              "width":4 }"##,
+    )
+    .unwrap()
+}
+
+#[test]
+fn set_tiles() {
+    let mut layer = grid();
+    assert_eq!(layer.set(1, 2, 0x8000_0005), Some(Gid(10)));
+    assert_eq!(layer.get(1, 2), Some(Gid(0x8000_0005)));
+    assert_eq!(layer.data[9], Gid(0x8000_0005));
+    assert_eq!(layer.set(4, 0, 1), None);
+    assert_eq!(layer.set(-1, 0, 1), None);
+
+    // An infinite layer grows a chunk where there isn't one, the size of
+    // the others
+    let mut infinite = infinite_layer();
+    assert_eq!(infinite.set(-1, -1, 7), Some(Gid(2)));
+    assert_eq!(infinite.set(-20, 5, 7), Some(Gid(0)));
+    assert_eq!(infinite.get(-20, 5), Some(Gid(7)));
+    let chunk = infinite.chunks.as_ref().unwrap().last().unwrap();
+    assert_eq!(
+        (chunk.x, chunk.y, chunk.width, chunk.height),
+        (-20, 4, 2, 2)
+    );
+
+    // Or Tiled's default for the first
+    let mut empty = infinite_layer();
+    empty.chunks = Some(Vec::new());
+    assert_eq!(empty.set(-20, 5, 7), Some(Gid(0)));
+    let chunk = empty.chunks.as_ref().unwrap().last().unwrap();
+    assert_eq!((chunk.x, chunk.y, chunk.width), (-32, 0, 16));
+
+    // Or of the size the map's editor settings give
+    let size = ChunkSize {
+        width: 32,
+        height: 8,
+    };
+    assert_eq!(infinite.set_in_new_chunk(40, -3, 7, size), Some(Gid(0)));
+    let chunk = infinite.chunks.as_ref().unwrap().last().unwrap();
+    assert_eq!((chunk.x, chunk.y), (32, -8));
+    assert_eq!((chunk.width, chunk.height, chunk.data.len()), (32, 8, 256));
+}
+
+#[test]
+fn rows_and_columns() {
+    let layer = grid();
+    let row: Vec<u32> = layer.row(1).map(Gid::raw).collect();
+    assert_eq!(row, vec![0, 6, 7, 8]);
+    let column: Vec<u32> = layer.column(3).map(Gid::raw).collect();
+    assert_eq!(column, vec![4, 8, 0]);
+    assert_eq!(layer.row(3).filter(|gid| !gid.is_empty()).count(), 0);
+
+    // The gaps between chunks are empty
+    let infinite = infinite_layer();
+    let row: Vec<u32> = infinite.row(-1).map(Gid::raw).collect();
+    assert_eq!(row, vec![0, 2, 0, 0, 0, 0]);
+}
+
+#[test]
+fn regions() {
+    let layer = grid();
+    let region = layer.region(TileRect {
+        x: 1,
+        y: 1,
+        width: 2,
+        height: 5,
+    });
+    assert_eq!((region.width(), region.height()), (2, 5));
+    assert_eq!(region.get(0, 0), Some(Gid(6)));
+    assert_eq!(region.get(1, 1), Some(Gid(11)));
+    assert_eq!(region.get(2, 0), None);
+    // Within the region, but past the layer
+    assert_eq!(region.get(0, 2), None);
+    assert_eq!(
+        region.iter_cells().collect::<Vec<_>>(),
+        vec![
+            (0, 0, Gid(6)),
+            (1, 0, Gid(7)),
+            (0, 1, Gid(10)),
+            (1, 1, Gid(11))
+        ]
+    );
+
+    let cells: Vec<_> = layer.iter_cells().map(|(x, y, _)| (x, y)).collect();
+    assert_eq!(cells.len(), 10);
+    assert!(!cells.contains(&(0, 1)));
+}
+
+const NESTED: &str = r##"
+    {
+      "height":2,
+      "infinite":false,
+      "layers":[
+        { "data":[1, 2, 3, 4], "height":2, "id":1, "name":"ground",
+          "opacity":1, "type":"tilelayer", "visible":true, "width":2 },
+        { "id":2, "layers":[
+            { "id":3, "layers":[
+                { "data":[1, 0, 0, 1], "height":2, "id":4, "name":"trees",
+                  "offsetx":1, "opacity":0.5, "tintcolor":"#40ff8000",
+                  "type":"tilelayer", "visible":true, "width":2 }],
+              "name":"inner", "offsety":4, "opacity":0.5,
+              "parallaxx":0.5, "type":"group", "visible":false },
+            { "draworder":"topdown", "id":5, "name":"things",
+              "objects":[
+                { "height":0, "id":1, "name":"spawn", "rotation":0,
+                  "type":"marker", "visible":true, "width":0, "x":1, "y":2 },
+                { "height":0, "id":2, "name":"exit", "rotation":0,
+                  "type":"marker", "visible":true, "width":0, "x":3, "y":4 },
+                { "class":"marker", "height":0, "id":3, "name":"spawn",
+                  "rotation":0, "type":"", "visible":true, "width":0, "x":5,
+                  "y":6 }],
+              "opacity":1, "type":"objectgroup", "visible":true }],
+          "name":"outer", "offsetx":10, "opacity":1, "parallaxx":0.5,
+          "tintcolor":"#ff8040c0", "type":"group", "visible":true },
+        { "id":6, "layers":[], "name":"things", "opacity":1,
+          "type":"group", "visible":true }],
+      "orientation":"orthogonal",
+      "tileheight":32,
+      "tilesets":[],
+      "tilewidth":32,
+      "width":2
+    }"##;
+
+#[test]
+fn layers_inherit_from_groups() {
+    let map = Map::load_from_str(NESTED).unwrap();
+    let layers: Vec<_> = map.iter_layers().collect();
+    let names: Vec<_> = layers.iter().map(|info| &info.layer.name).collect();
+    assert_eq!(names, ["ground", "trees", "things"]);
+
+    let ground = &layers[0];
+    assert!(ground.path.is_empty());
+    assert_eq!(ground.offset, Vec2 { x: 0.0, y: 0.0 });
+    assert_eq!(ground.opacity, 1.0);
+    assert!(ground.visible);
+    assert_eq!(ground.tint_color, None);
+    assert_eq!(ground.parallax, Vec2 { x: 1.0, y: 1.0 });
+
+    let trees = &layers[1];
+    assert_eq!(trees.path, ["outer", "inner"]);
+    assert_eq!(trees.offset, Vec2 { x: 11.0, y: 4.0 });
+    assert_eq!(trees.opacity, 0.25);
+    // Hidden by its group
+    assert!(!trees.visible);
+    assert_eq!(trees.parallax, Vec2 { x: 0.25, y: 1.0 });
+    // Each channel of the two tints multiplied
+    let outer = Color(128, 64, 192, 255);
+    assert_eq!(trees.layer.tint_color, Some(Color(255, 128, 0, 64)));
+    assert_eq!(trees.tint_color, Some(Color(128, 32, 0, 64)));
+
+    let things = &layers[2];
+    assert_eq!(things.path, ["outer"]);
+    assert!(things.visible);
+    assert_eq!(things.tint_color, Some(outer));
+}
+
+#[test]
+fn find_layers_and_objects() {
+    let map = Map::load_from_str(NESTED).unwrap();
+    let name = |layer: Option<&Layer>| layer.map(|layer| layer.name.clone());
+    assert_eq!(name(map.layer_by_name("trees")).as_deref(), Some("trees"));
+    assert_eq!(name(map.layer_by_name("inner")).as_deref(), Some("inner"));
+    // The first of the two named "things" is in a group
+    assert_eq!(map.layer_by_name("things").unwrap().id, Some(5));
+    assert!(map.layer_by_name("missing").is_none());
+
+    assert_eq!(name(map.layer_by_id(4)).as_deref(), Some("trees"));
+    assert_eq!(name(map.layer_by_id(2)).as_deref(), Some("outer"));
+    assert!(map.layer_by_id(7).is_none());
+
+    assert_eq!(map.layer_by_path("outer/inner/trees").unwrap().id, Some(4));
+    assert_eq!(map.layer_by_path("outer/things").unwrap().id, Some(5));
+    assert_eq!(map.layer_by_path("things").unwrap().id, Some(6));
+    assert!(map.layer_by_path("outer/trees").is_none());
+    assert!(map.layer_by_path("ground/trees").is_none());
+
+    assert_eq!(map.object_by_id(2).unwrap().name, "exit");
+    assert!(map.object_by_id(4).is_none());
+    let ids = |objects: Vec<&Object>| -> Vec<_> {
+        objects.iter().map(|object| object.id.unwrap()).collect()
+    };
+    assert_eq!(ids(map.objects_by_name("spawn")), [1, 3]);
+    // The third has its type as its class
+    assert_eq!(ids(map.objects_by_type("marker")), [1, 2, 3]);
+    assert!(map.objects_by_name("nobody").is_empty());
+}
+
+#[test]
+fn lookups_survive_changed_layers() {
+    let mut map = Map::load_from_str(NESTED).unwrap();
+    map.layers.swap(0, 2);
+    // Out of date, so found by searching
+    assert_eq!(map.layer_by_id(1).unwrap().name, "ground");
+    assert_eq!(map.object_by_id(3).unwrap().name, "spawn");
+
+    // Not in the index, so found by searching
+    let mut ground = map.layers[2].clone();
+    ground.id = Some(7);
+    ground.name = String::from("added");
+    map.layers.push(ground);
+    assert_eq!(map.layer_by_id(7).unwrap().name, "added");
+    assert_eq!(map.layer_by_name("added").unwrap().id, Some(7));
+    let things = match &mut map.layers[1].layer_type {
+        LayerType::Group { layers } => &mut layers[1],
+        _ => panic!("Second layer should be the outer group"),
+    };
+    let group = match &mut things.layer_type {
+        LayerType::ObjectGroup(group) => group,
+        _ => panic!("expected an object group"),
+    };
+    let mut object = group.objects[0].clone();
+    object.id = Some(9);
+    object.name = String::from("newcomer");
+    object.custom_type = String::from("visitor");
+    group.objects.push(object);
+    assert_eq!(map.object_by_id(9).unwrap().name, "newcomer");
+    assert_eq!(map.objects_by_name("newcomer").len(), 1);
+    assert_eq!(map.objects_by_type("visitor").len(), 1);
+    assert!(map.objects_by_name("nobody").is_empty());
+
+    map.reindex();
+    assert_eq!(map.layer_by_id(7).unwrap().name, "added");
+    assert_eq!(map.object_by_id(9).unwrap().name, "newcomer");
+}
+
+#[test]
+fn layer_attributes() {
+    let data = r##"
+        {
+          "class":"scenery",
+          "color":"#ff0000",
+          "draworder":"index",
+          "id":8,
+          "locked":true,
+          "name":"objects",
+          "objects":[],
+          "opacity":1,
+          "parallaxx":0.5,
+          "parallaxy":2,
+          "tintcolor":"#80ffffff",
+          "type":"objectgroup",
+          "visible":true
+        }"##;
+    let layer: Layer = serde_json::from_str(data).unwrap();
+    assert_eq!(layer.id, Some(8));
+    assert_eq!(layer.class, "scenery");
+    assert!(layer.locked);
+    assert_eq!((layer.parallax_x, layer.parallax_y), (0.5, 2.0));
+    assert_eq!(layer.tint(), Color(255, 255, 255, 128));
+    match &layer.layer_type {
+        LayerType::ObjectGroup(group) => {
+            assert_eq!(group.color, Some(Color(255, 0, 0, 255)));
+        }
+        _ => panic!("expected an object group"),
+    }
+    let saved: Layer =
+        serde_json::from_str(&serde_json::to_string(&layer).unwrap()).unwrap();
+    assert_eq!(saved, layer);
+
+    // Without them, as Tiled leaves them out at their defaults
+    let data = r##"
+        { "image":"bg.png", "name":"image", "opacity":1,
+          "type":"imagelayer", "visible":true }"##;
+    let layer: Layer = serde_json::from_str(data).unwrap();
+    assert_eq!(layer.id, None);
+    assert_eq!(layer.class, "");
+    assert!(!layer.locked);
+    assert_eq!((layer.parallax_x, layer.parallax_y), (1.0, 1.0));
+    assert_eq!(layer.tint_color, None);
+    assert_eq!(layer.tint(), Color(255, 255, 255, 255));
+}
+
+const IMAGES: &str = r##"
+    {
+      "height":4,
+      "infinite":false,
+      "layers":[
+        { "image":"sky.png", "imageheight":50, "imagewidth":100,
+          "name":"sky", "offsetx":10, "offsety":20, "opacity":1,
+          "repeatx":true, "type":"imagelayer", "visible":true },
+        { "layers":[
+            { "image":"clouds.png", "imageheight":50, "imagewidth":100,
+              "name":"clouds", "opacity":1, "parallaxx":0.5,
+              "repeatx":true, "repeaty":true, "tintcolor":"#ff00ff00",
+              "type":"imagelayer", "visible":true }],
+          "name":"far", "offsetx":-10, "opacity":1, "type":"group",
+          "visible":true },
+        { "image":"sign.png", "imageheight":50, "imagewidth":100,
+          "name":"sign", "opacity":1, "type":"imagelayer",
+          "visible":true },
+        { "image":"unknown.png", "name":"unknown", "opacity":1,
+          "repeatx":true, "type":"imagelayer", "visible":true }],
+      "orientation":"orthogonal",
+      "parallaxoriginx":50,
+      "tileheight":32,
+      "tilesets":[],
+      "tilewidth":32,
+      "width":4
+    }"##;
+
+#[test]
+fn image_layer_fields() {
+    let map = Map::load_from_str(IMAGES).unwrap();
+    let clouds = map.layer_by_name("clouds").unwrap();
+    assert_eq!(clouds.tint(), Color(0, 255, 0, 255));
+    match &clouds.layer_type {
+        LayerType::ImageLayer(image) => {
+            assert_eq!((image.image_width, image.image_height), (100, 50));
+            assert!(image.repeat_x && image.repeat_y);
+        }
+        _ => panic!("expected an image layer"),
+    }
+    match &map.layer_by_name("unknown").unwrap().layer_type {
+        LayerType::ImageLayer(image) => {
+            assert_eq!((image.image_width, image.image_height), (0, 0));
+            assert!(image.repeat_x && !image.repeat_y);
+        }
+        _ => panic!("expected an image layer"),
+    }
+    let saved: Map = serde_json::from_str(&map.to_string().unwrap()).unwrap();
+    assert_eq!(saved, map);
+}
+
+#[test]
+fn image_layer_rects() {
+    let map = Map::load_from_str(IMAGES).unwrap();
+    let rect = |x, y, width, height| Rect {
+        x,
+        y,
+        width,
+        height,
+    };
+    let at = |x, y| rect(x, y, 100.0, 50.0);
+    let view = rect(0.0, 0.0, 250.0, 100.0);
+
+    // Repeated across, at its offset
+    let sky = map.layer_by_name("sky").unwrap();
+    assert_eq!(
+        map.image_layer_rects(sky, view),
+        [
+            at(-90.0, 20.0),
+            at(10.0, 20.0),
+            at(110.0, 20.0),
+            at(210.0, 20.0)
+        ]
+    );
+    // Out of view
+    assert!(map
+        .image_layer_rects(sky, rect(0.0, 100.0, 250.0, 100.0))
+        .is_empty());
+
+    // The view is centred at 125 with the parallax origin at 50, so the
+    // clouds move half of 75 along with it
+    let clouds = map.layer_by_name("clouds").unwrap();
+    let rects = map.image_layer_rects(clouds, rect(0.0, 0.0, 250.0, 60.0));
+    assert_eq!(rects.len(), 8);
+    assert_eq!(rects[0], at(-72.5, 0.0));
+    assert_eq!(rects[3], at(227.5, 0.0));
+    assert_eq!(rects[4], at(-72.5, 50.0));
+
+    let sign = map.layer_by_name("sign").unwrap();
+    assert_eq!(map.image_layer_rects(sign, view), [at(0.0, 0.0)]);
+
+    // Without a size there's nothing to repeat
+    let unknown = map.layer_by_name("unknown").unwrap();
+    assert!(map.image_layer_rects(unknown, view).is_empty());
+    // Nor are other layers or layers of another map images of it
+    assert!(map.image_layer_rects(&sign.clone(), view).is_empty());
+    let far = map.layer_by_name("far").unwrap();
+    assert!(map.image_layer_rects(far, view).is_empty());
+}
@@ -1,12 +1,18 @@
+// The test maps store a "pi" property of exactly 3.14
+#![allow(clippy::approx_constant)]
+
 use std::path::Path;
-use tiled_json_rs::{Color, LayerType, Map, TiledValue};
+use tiled_json_rs::{
+    Color, Compression, Encoding, Gid, LayerType, Map, ObjectType, TileSet,
+    TiledValue,
+};
 
 #[test]
 fn test_load_file_small_base64_uncompressed() {
     let p = Path::new("tests/data/base64.json");
-    let level = Map::load_from_file(&p).unwrap();
+    let level = Map::load_from_file(p).unwrap();
 
-    let image_path = &level.tile_sets[0].image;
+    let image_path = &level.tile_sets[0].internal().image;
     assert!(image_path.ends_with("numbers.png"));
 
     // Group layer == 0
@@ -27,7 +33,7 @@ fn test_load_file_small_base64_uncompressed() {
     }
 
     // Check tile object groups
-    let tile = &level.tile_sets[0].tiles.as_ref().unwrap()[0];
+    let tile = &level.tile_sets[0].internal().tiles[&0];
     let objgrp = &tile.object_group.as_ref().unwrap().objects;
     assert_eq!(objgrp[0].height, 25.25);
 
@@ -37,9 +43,9 @@ fn test_load_file_small_base64_uncompressed() {
 #[test]
 fn test_load_file_polys_csv() {
     let p = Path::new("tests/data/csv.json");
-    let level = Map::load_from_file(&p).unwrap();
+    let level = Map::load_from_file(p).unwrap();
 
-    let image_path = &level.tile_sets[0].image;
+    let image_path = &level.tile_sets[0].internal().image;
     assert!(image_path.ends_with("numbers.png"));
 
     assert_eq!(level.layers.len(), 2);
@@ -51,7 +57,7 @@ fn test_load_file_polys_csv() {
     assert_eq!(level.properties["bool_false"], TiledValue::Bool(false));
     assert_eq!(
         level.properties["colour"],
-        TiledValue::Color(Color(161, 161, 161, 204))
+        TiledValue::Color(Color(26, 26, 26, 204))
     );
 
     // Group layer == 0
@@ -81,9 +87,9 @@ fn test_load_file_polys_csv() {
 #[test]
 fn test_load_file_external_tileset() {
     let p = Path::new("tests/data/external_tileset.json");
-    let level = Map::load_from_file(&p).unwrap();
+    let level = Map::load_from_file(p).unwrap();
 
-    let image_path = &level.tile_sets[0].image;
+    let image_path = &level.tile_sets[0].internal().image;
     assert!(image_path.ends_with("numbers.png"));
 
     // Group layer == 0
@@ -104,7 +110,7 @@ fn test_load_file_external_tileset() {
     }
 
     // Check tile object groups
-    let tile = &level.tile_sets[0].tiles.as_ref().unwrap()[0];
+    let tile = &level.tile_sets[0].internal().tiles[&0];
     let objgrp = &tile.object_group.as_ref().unwrap().objects;
     assert_eq!(objgrp[0].height, 25.25);
 
@@ -114,9 +120,9 @@ fn test_load_file_external_tileset() {
 #[test]
 fn test_load_file_with_object_templates() {
     let p = Path::new("tests/data/external_objects.json");
-    let level = Map::load_from_file(&p).unwrap();
+    let level = Map::load_from_file(p).unwrap();
 
-    let image_path = &level.tile_sets[0].image;
+    let image_path = &level.tile_sets[0].internal().image;
     assert!(image_path.ends_with("numbers.png"));
 
     // Group layer == 0
@@ -137,9 +143,193 @@ fn test_load_file_with_object_templates() {
     }
 
     // Check tile object groups
-    let tile = &level.tile_sets[0].tiles.as_ref().unwrap()[0];
+    let tile = &level.tile_sets[0].internal().tiles[&0];
     let objgrp = &tile.object_group.as_ref().unwrap().objects;
     assert_eq!(objgrp[0].height, 25.25);
 
     assert_eq!(level.layers.len(), 2);
+
+    // Templates are found relative to the map, not the working directory
+    if let LayerType::ObjectGroup(group) = &level.layers[1].layer_type {
+        let tile = &group.objects[5];
+        assert_eq!(tile.name, "tile");
+        assert_eq!(tile.rotation, 45.0);
+        assert_eq!((tile.x, tile.y), (768.0, 176.0));
+        assert_eq!(tile.gid.map(Gid::id), Some(1));
+
+        let point = &group.objects[7];
+        assert_eq!(point.object_type, ObjectType::Point);
+        assert_eq!(point.id, Some(12));
+        // A field set by the instance is kept
+        assert_eq!(group.objects[8].name, "middle");
+        assert_eq!(group.objects[8].object_type, ObjectType::Point);
+    } else {
+        panic!("Second layer should be an object group");
+    }
+}
+
+#[test]
+fn test_load_str_leaves_templates_unresolved() {
+    let data =
+        std::fs::read_to_string("tests/data/external_objects.json").unwrap();
+    let level = Map::load_from_str(&data).unwrap();
+    if let LayerType::ObjectGroup(group) = &level.layers[1].layer_type {
+        let point = &group.objects[8];
+        assert_eq!(
+            point.object_type,
+            ObjectType::Template("point_template.json".to_string())
+        );
+        assert_eq!(point.name, "middle");
+
+        // Only the fields the instance sets are written back
+        let json = serde_json::to_value(point).unwrap();
+        assert_eq!(json["template"], "point_template.json");
+        assert_eq!(json["name"], "middle");
+        assert!(json.get("rotation").is_none());
+    } else {
+        panic!("Second layer should be an object group");
+    }
+}
+
+#[test]
+fn test_template_instances_override_and_merge() {
+    let dir = std::env::temp_dir()
+        .join(format!("tiled-json-rs-merge-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("templates")).unwrap();
+    std::fs::write(
+        dir.join("templates/crate.json"),
+        r##"{ "object":
+                { "gid":2147483650, "height":32, "id":3, "name":"crate",
+                  "properties":[
+                    { "name":"hp", "type":"int", "value":10 },
+                    { "name":"loot", "type":"string", "value":"gold" }],
+                  "rotation":0, "type":"prop", "visible":true, "width":32 },
+              "tileset":{ "firstgid":1, "source":"../tilesets/items.json" },
+              "type":"template" }"##,
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("templates/marker.json"),
+        r##"{ "object":
+                { "height":0, "id":1, "name":"marker", "point":true,
+                  "rotation":0, "type":"", "visible":true, "width":0 },
+              "type":"template" }"##,
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("map.json"),
+        r##"{ "height":2, "orientation":"orthogonal", "tileheight":32,
+              "tilewidth":32, "width":2,
+              "tilesets":[
+                { "firstgid":1, "source":"tilesets/terrain.json" },
+                { "firstgid":101, "source":"./tilesets/items.json" }],
+              "layers":[
+                { "name":"objects", "opacity":1, "type":"objectgroup",
+                  "visible":true,
+                  "objects":[
+                    { "id":1, "name":"big crate", "height":64,
+                      "properties":[
+                        { "name":"hp", "type":"int", "value":20 },
+                        { "name":"locked", "type":"bool", "value":true }],
+                      "template":"templates/crate.json", "x":0, "y":0 },
+                    { "id":2, "gid":5, "template":"templates/crate.json",
+                      "x":0, "y":0 },
+                    { "ellipse":true, "id":3, "width":16, "height":16,
+                      "template":"templates/marker.json", "x":0, "y":0 }]
+                }]
+            }"##,
+    )
+    .unwrap();
+    let level = Map::load_from_file_unresolved(&dir.join("map.json")).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let objects = match &level.layers[0].layer_type {
+        LayerType::ObjectGroup(group) => &group.objects,
+        _ => panic!("Layer should be an object group"),
+    };
+
+    // The instance's fields win, the template fills in the rest
+    let big = &objects[0];
+    assert_eq!(big.id, Some(1));
+    assert_eq!(big.name, "big crate");
+    assert_eq!(big.custom_type, "prop");
+    assert_eq!((big.width, big.height), (32.0, 64.0));
+    assert_eq!(big.properties["hp"], TiledValue::Int(20));
+    assert_eq!(big.properties["locked"], TiledValue::Bool(true));
+    assert_eq!(
+        big.properties["loot"],
+        TiledValue::String("gold".to_string())
+    );
+
+    // The template's tile is the second of its tileset, which the map has
+    // at 101, and it keeps its flip
+    let gid = big.gid.unwrap();
+    assert_eq!(gid.id(), 102);
+    assert!(gid.flip_h());
+    // A GID set by the instance is already the map's
+    assert_eq!(objects[1].gid, Some(Gid(5)));
+
+    // A shape set by the instance replaces the template's
+    assert_eq!(objects[2].object_type, ObjectType::Ellipse);
+    assert_eq!(objects[2].name, "marker");
+}
+
+fn group_tile_data(level: &Map) -> &Vec<Gid> {
+    if let LayerType::Group { layers } = &level.layers[0].layer_type {
+        if let LayerType::TileLayer(tiles) = &layers[1].layer_type {
+            return &tiles.data;
+        }
+    }
+    panic!("Second layer in group should be tile layer")
+}
+
+#[test]
+fn test_load_file_compressed_matches_uncompressed() {
+    let plain =
+        Map::load_from_file(Path::new("tests/data/base64.json")).unwrap();
+    let expected = group_tile_data(&plain);
+    assert_eq!(expected.len(), 504);
+
+    let mut files = Vec::new();
+    if cfg!(feature = "zlib") {
+        files.push(("tests/data/b64zlib.json", Compression::Zlib));
+    }
+    if cfg!(feature = "gzip") {
+        files.push(("tests/data/b64gzip.json", Compression::Gzip));
+    }
+    if cfg!(feature = "zstd") {
+        files.push(("tests/data/b64zstd.json", Compression::Zstd));
+    }
+
+    for (file, compression) in files {
+        let level = Map::load_from_file(Path::new(file)).unwrap();
+        assert_eq!(group_tile_data(&level), expected, "{}", file);
+        if let LayerType::Group { layers } = &level.layers[0].layer_type {
+            if let LayerType::TileLayer(tiles) = &layers[1].layer_type {
+                assert_eq!(tiles.encoding, Encoding::Base64);
+                assert_eq!(tiles.compression, Some(compression));
+            }
+        }
+    }
+}
+
+#[test]
+fn test_load_file_external_tileset_resolution() {
+    let p = Path::new("tests/data/external_tileset.json");
+    let level = Map::load_from_file(p).unwrap();
+    let tile_set = level.tile_sets[0].internal();
+    assert_eq!(tile_set.first_gid, 1);
+    assert_eq!(tile_set.name, "base");
+    assert_eq!(level.tileset_name(1), Some("base"));
+    assert_eq!(level.tileset_image_path(9), Some(Path::new("numbers.png")));
+
+    let level = Map::load_from_file_unresolved(p).unwrap();
+    if let TileSet::External(external) = &level.tile_sets[0] {
+        assert_eq!(external.first_gid, 1);
+        assert_eq!(external.source, Path::new("tileset.json"));
+    } else {
+        panic!("Tileset should have been left as a reference");
+    }
+    assert!(level.tile_sets[0].as_internal().is_none());
+    assert_eq!(level.tileset_name(1), None);
 }
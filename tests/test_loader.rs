@@ -0,0 +1,141 @@
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use tiled_json_rs::{
+    Error, External, InMemory, LayerType, Map, ObjectType, TileSet,
+};
+
+fn assets() -> InMemory {
+    InMemory::new()
+        .with(
+            "maps/level.json",
+            include_str!("data/external_objects.json")
+                .replace("\"tileset.json\"", "\"../tilesets/tileset.json\"")
+                .replace(
+                    "\"point_template.json\"",
+                    "\"../point_template.json\"",
+                )
+                .replace("\"tile_template.json\"", "\"../tile_template.json\""),
+        )
+        .with(
+            "point_template.json",
+            &include_bytes!("data/point_template.json")[..],
+        )
+        .with(
+            "tile_template.json",
+            include_str!("data/tile_template.json")
+                .replace("\"tileset.json\"", "\"tilesets/tileset.json\""),
+        )
+        .with(
+            "tilesets/tileset.json",
+            &include_bytes!("data/tileset.json")[..],
+        )
+}
+
+#[test]
+fn in_memory_map_matches_file() {
+    let mut from_memory =
+        Map::load_with(Path::new("maps/level.json"), &assets()).unwrap();
+    let from_file =
+        Map::load_from_file(Path::new("tests/data/external_objects.json"))
+            .unwrap();
+    // Template paths stay as the map gives them
+    if let LayerType::ObjectGroup(group) = &mut from_memory.layers[1].layer_type
+    {
+        for template in group.objects.iter_mut().flat_map(|o| &mut o.template) {
+            let path = template.path.strip_prefix("../").unwrap();
+            template.path = path.to_string();
+        }
+    }
+    assert_eq!(from_memory.layers, from_file.layers);
+
+    let tile_set = from_memory.tile_sets[0].internal();
+    assert_eq!(tile_set.name, from_file.tile_sets[0].internal().name);
+    // Image paths stay relative to the map
+    assert!(tile_set.image.starts_with("../tilesets"));
+}
+
+#[test]
+fn unresolved_load_still_applies_templates() {
+    let map =
+        Map::load_unresolved_with(Path::new("maps/level.json"), &assets())
+            .unwrap();
+    assert!(matches!(map.tile_sets[0], TileSet::External(_)));
+    if let LayerType::ObjectGroup(group) = &map.layers[1].layer_type {
+        assert_eq!(group.objects[7].object_type, ObjectType::Point);
+    } else {
+        panic!("Second layer should be an object group");
+    }
+}
+
+#[test]
+fn closures_are_loaders() {
+    let read = RefCell::new(Vec::new());
+    let loader = |path: &Path| {
+        read.borrow_mut().push(path.to_path_buf());
+        std::fs::read(path)
+    };
+    Map::load_with(Path::new("tests/data/external_objects.json"), &loader)
+        .unwrap();
+
+    // Each template is only read once, however many objects use it
+    let read = read.into_inner();
+    let dir = Path::new("tests/data");
+    assert_eq!(read[0], dir.join("external_objects.json"));
+    for file in ["tile_template.json", "point_template.json", "tileset.json"] {
+        let count = read.iter().filter(|p| p.ends_with(file)).count();
+        assert_eq!(count, 1, "{} read {} times", file, count);
+    }
+}
+
+#[test]
+fn missing_in_memory_file_is_a_missing_reference() {
+    let loader = InMemory::new().with(
+        "level.json",
+        include_bytes!("data/external_tileset.json").to_vec(),
+    );
+    let err = Map::load_with(Path::new("level.json"), &loader)
+        .expect_err("should fail");
+    match &err {
+        Error::MissingReference { reference, .. } => {
+            assert_eq!(reference, &PathBuf::from("tileset.json"));
+        }
+        e => panic!("expected a missing reference, got {:?}", e),
+    }
+    assert_eq!(err.json_path(), Some("tilesets[0]"));
+
+    let err = Map::load_with(Path::new("other.json"), &loader)
+        .expect_err("should fail");
+    assert!(matches!(err, Error::Io { .. }));
+}
+
+#[test]
+fn external_image_collection_paths() {
+    let loader = InMemory::new().with(
+        "tilesets/props.json",
+        r#"{
+         "columns":0,
+         "margin":0,
+         "name":"props",
+         "spacing":0,
+         "tilecount":1,
+         "tileheight":64,
+         "tiles":[
+              { "id":0, "image":"barrel.png", "imageheight":48,
+                "imagewidth":32 }],
+         "tilewidth":32
+        }"#,
+    );
+    let tile_set = External {
+        first_gid: 1,
+        source: PathBuf::from("tilesets/props.json"),
+    }
+    .load_with(Path::new(""), &loader)
+    .unwrap();
+    // Only the tiles have images to rewrite
+    assert!(tile_set.is_image_collection());
+    assert_eq!(tile_set.image, PathBuf::new());
+    assert_eq!(
+        tile_set.tile_image_path(0),
+        Some(Path::new("tilesets/barrel.png"))
+    );
+}
@@ -0,0 +1,80 @@
+//! A lightweight stand-in for the fuzz target in `fuzz/`, run with the
+//! normal test suite: damaged copies of the test maps must give an `Err`,
+//! never a panic.
+
+use tiled_json_rs::Map;
+
+const MAPS: &[&str] = &[
+    include_str!("data/base64.json"),
+    include_str!("data/b64zlib.json"),
+    include_str!("data/csv.json"),
+];
+
+#[cfg(feature = "xml")]
+#[test]
+fn truncated_tmx_does_not_panic() {
+    let map = include_str!("data/csv.tmx");
+    for end in (0..map.len()).step_by(7) {
+        if map.is_char_boundary(end) {
+            let _ = Map::load_from_str(&map[..end]);
+        }
+    }
+}
+
+#[test]
+fn truncated_maps_do_not_panic() {
+    for map in MAPS {
+        for end in (0..map.len()).step_by(7) {
+            if map.is_char_boundary(end) {
+                let _ = Map::load_from_str(&map[..end]);
+            }
+        }
+    }
+}
+
+#[test]
+fn corrupted_maps_do_not_panic() {
+    let replacements = ["", "\"", "#", "0", "-1", "\"!\"", "[]", "{}", "é"];
+    for map in MAPS {
+        for start in (0..map.len()).step_by(13) {
+            if !map.is_char_boundary(start) || !map.is_char_boundary(start + 1)
+            {
+                continue;
+            }
+            for replacement in &replacements {
+                let corrupted = format!(
+                    "{}{}{}",
+                    &map[..start],
+                    replacement,
+                    &map[start + 1..]
+                );
+                let _ = Map::load_from_str(&corrupted);
+            }
+        }
+    }
+}
+
+#[test]
+fn odd_length_tile_data_is_an_error() {
+    let map = r##"
+        {
+          "height":1,
+          "layers":[
+            {
+              "data":"AQAAAAIA",
+              "encoding":"base64",
+              "height":1,
+              "name":"ground",
+              "opacity":1,
+              "type":"tilelayer",
+              "visible":true,
+              "width":2
+            }],
+          "orientation":"orthogonal",
+          "tileheight":32,
+          "tilesets":[],
+          "tilewidth":32,
+          "width":2
+        }"##;
+    assert!(Map::load_from_str(map).is_err());
+}
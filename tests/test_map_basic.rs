@@ -1,4 +1,7 @@
-use tiled_json_rs::{Color, Map, Orientation, RenderOrder, TiledValue};
+use std::path::PathBuf;
+use tiled_json_rs::{
+    ChunkSize, Color, Map, Orientation, RenderOrder, TiledValue, Version,
+};
 
 #[test]
 fn parse_map_basic() {
@@ -32,7 +35,7 @@ fn parse_map_basic() {
 
     let m: Map = serde_json::from_str(data).expect("fail");
 
-    assert_eq!(&m.background_color, &Color(86, 102, 118, 255));
+    assert_eq!(&m.background_color, &Color(101, 102, 103, 255));
 
     assert!(m.hex_side_length.is_none());
 
@@ -62,3 +65,159 @@ fn parse_map_basic() {
     assert_eq!(m.height, 4);
     assert_eq!(m.width, 4);
 }
+
+fn tile_set(name: &str, first_gid: u32, tile_count: u32) -> String {
+    format!(
+        r#"{{ "columns":4, "firstgid":{}, "image":"{}.png",
+              "imageheight":128, "imagewidth":128, "name":"{}",
+              "tilecount":{}, "tileheight":32, "tilewidth":32,
+              "tiles":[{{ "id":1, "type":"special" }}] }}"#,
+        first_gid, name, name, tile_count
+    )
+}
+
+fn map_with_tile_sets(tile_sets: &[String]) -> Map {
+    Map::load_from_str(&format!(
+        r#"{{ "height":1, "layers":[], "orientation":"orthogonal",
+              "tileheight":32, "tilesets":[{}], "tilewidth":32,
+              "width":1 }}"#,
+        tile_sets.join(",")
+    ))
+    .unwrap()
+}
+
+#[test]
+fn resolve_gid_finds_the_tileset() {
+    // Listed out of order, and with a gap between `b` and `c`
+    let map = map_with_tile_sets(&[
+        tile_set("c", 100, 16),
+        tile_set("a", 1, 16),
+        tile_set("b", 17, 16),
+    ]);
+    let name = |gid: u32| map.resolve_gid(gid).map(|(set, ..)| &set.name);
+    assert_eq!(name(1).map(String::as_str), Some("a"));
+    assert_eq!(name(16).map(String::as_str), Some("a"));
+    assert_eq!(name(17).map(String::as_str), Some("b"));
+    assert_eq!(name(0x8000_0020).map(String::as_str), Some("b"));
+    assert_eq!(name(115).map(String::as_str), Some("c"));
+    for gid in [0, 33, 99, 116] {
+        assert_eq!(name(gid), None, "{}", gid);
+    }
+
+    let (set, lid, tile) = map.resolve_gid(18).unwrap();
+    assert_eq!((set.name.as_str(), lid), ("b", 1));
+    assert_eq!(tile.and_then(|t| t.tile_type.as_deref()), Some("special"));
+    assert!(map.resolve_gid(19).unwrap().2.is_none());
+}
+
+#[test]
+fn lookups_follow_changed_tile_sets() {
+    let mut map = map_with_tile_sets(&[tile_set("a", 1, 16)]);
+    assert_eq!(map.tileset_name(20), None);
+
+    let other = map_with_tile_sets(&[tile_set("b", 17, 16)]);
+    map.tile_sets.extend(other.tile_sets);
+    assert_eq!(map.tileset_name(20), Some("b"));
+    map.reindex();
+    assert_eq!(map.tileset_name(20), Some("b"));
+
+    map.tile_sets.remove(1);
+    assert_eq!(map.tileset_name(20), None);
+}
+
+#[test]
+fn map_level_fields() {
+    let data = r##"
+        {
+          "class":"level",
+          "compressionlevel":3,
+          "editorsettings":{
+            "chunksize":{ "height":8, "width":32 },
+            "export":{ "format":"json", "target":"level.json" }
+          },
+          "height":4,
+          "infinite":false,
+          "layers":[],
+          "nextlayerid":3,
+          "nextobjectid":12,
+          "orientation":"orthogonal",
+          "parallaxoriginx":16,
+          "parallaxoriginy":-8.5,
+          "tileheight":32,
+          "tiledversion":"1.10.2",
+          "tilesets":[],
+          "tilewidth":32,
+          "version":"1.10",
+          "width":4
+        }"##;
+    let map = Map::load_from_str(data).unwrap();
+    assert_eq!(map.class, "level");
+    assert_eq!(map.compression_level, 3);
+    assert_eq!(map.next_layer_id, 3);
+    assert_eq!(map.next_object_id, 12);
+    assert_eq!((map.parallax_origin_x, map.parallax_origin_y), (16.0, -8.5));
+    let settings = map.editor_settings.clone().unwrap();
+    assert_eq!(
+        settings.chunk_size,
+        Some(ChunkSize {
+            width: 32,
+            height: 8
+        })
+    );
+    assert_eq!(map.chunk_size(), settings.chunk_size.unwrap());
+    let export = settings.export.unwrap();
+    assert_eq!(export.target.as_deref(), Some("level.json"));
+    assert_eq!(export.format.as_deref(), Some("json"));
+    assert_eq!(map.format_version(), Some(Version::new(1, 10, 0)));
+    assert_eq!(map.editor_version(), Some(Version::new(1, 10, 2)));
+
+    let saved: Map = serde_json::from_str(&map.to_string().unwrap()).unwrap();
+    assert_eq!(saved, map);
+}
+
+#[test]
+fn map_level_defaults() {
+    let map = Map::load_from_file(&PathBuf::from("tests/data/csv.json"))
+        .expect("Failed to load map");
+    // Written as a number by Tiled 1.2
+    assert_eq!(map.version, "1.2");
+    assert_eq!(map.format_version(), Some(Version::new(1, 2, 0)));
+    assert_eq!(map.editor_version(), Some(Version::new(1, 2, 1)));
+    assert!(map.format_version() < Some(Version::new(1, 10, 0)));
+    assert_eq!(map.compression_level, -1);
+    assert_eq!(map.class, "");
+    assert_eq!(map.parallax_origin_x, 0.0);
+    assert!(map.editor_settings.is_none());
+    assert_eq!(map.chunk_size(), ChunkSize::default());
+}
+
+#[test]
+fn versions() {
+    assert_eq!(Version::parse("1"), Some(Version::new(1, 0, 0)));
+    assert_eq!(Version::parse("1.9.0-beta"), Some(Version::new(1, 9, 0)));
+    assert_eq!(
+        Version::parse("2021.03.23"),
+        Some(Version::new(2021, 3, 23))
+    );
+    assert_eq!(Version::parse(""), None);
+    assert_eq!(Version::parse("1.x"), None);
+    assert_eq!(Version::parse("1.2.3.4"), None);
+    assert!(Version::new(1, 10, 0) > Version::new(1, 9, 2));
+    assert_eq!(Version::new(1, 10, 2).to_string(), "1.10.2");
+}
+
+#[test]
+fn new_ids_skip_those_in_use() {
+    let mut map = Map::load_from_file(&PathBuf::from("tests/data/csv.json"))
+        .expect("Failed to load map");
+    assert_eq!(map.new_layer_id(), 7);
+    assert_eq!(map.new_layer_id(), 8);
+    assert_eq!(map.next_layer_id, 9);
+
+    let used = map.new_object_id();
+    assert!(map.object_by_id(used).is_none());
+    map.next_object_id = 1;
+    // An id lower than those in use isn't given out again
+    let id = map.new_object_id();
+    assert!(id > 1 && map.object_by_id(id).is_none());
+}
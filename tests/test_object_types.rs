@@ -1,4 +1,7 @@
-use tiled_json_rs::{Object, TiledValue};
+use tiled_json_rs::{
+    Color, Gid, HorizontalAlign, Object, ObjectType, Text, TiledValue, Vec2,
+    VerticalAlign,
+};
 
 #[test]
 fn object() {
@@ -159,6 +162,99 @@ fn polyline() {
     assert!(serde_json::from_str::<Object>(data).is_ok());
 }
 
+#[test]
+fn fractional_polygon() {
+    // As exported by Tiled 1.4 after snapping to a fine grid
+    let data = r##"
+        {
+          "height":0,
+          "id":17,
+          "name":"",
+          "polygon":[
+          {
+            "x":0,
+            "y":0
+          },
+          {
+            "x":12.5,
+            "y":-3.25
+          },
+          {
+            "x":-0.333333,
+            "y":7.66667
+          }],
+          "rotation":0,
+          "type":"",
+          "visible":true,
+          "width":0,
+          "x":10.5,
+          "y":20.75
+        }"##;
+    let object: Object = serde_json::from_str(data).unwrap();
+    let points = match &object.object_type {
+        ObjectType::Polygon(points) => points,
+        other => panic!("expected a polygon, found {:?}", other),
+    };
+    assert_eq!(points[1], Vec2 { x: 12.5, y: -3.25 });
+    assert_eq!(
+        points[2],
+        Vec2 {
+            x: -0.333333,
+            y: 7.66667
+        }
+    );
+    assert_eq!(object.object_type.points(), Some(&points[..]));
+
+    // Whole pixels, as points used to be
+    let rounded: Vec<Vec2<i32>> = points.iter().map(|p| p.round()).collect();
+    assert_eq!(
+        rounded,
+        [
+            Vec2 { x: 0, y: 0 },
+            Vec2 { x: 13, y: -3 },
+            Vec2 { x: 0, y: 8 }
+        ]
+    );
+
+    let saved: Object =
+        serde_json::from_str(&serde_json::to_string(&object).unwrap()).unwrap();
+    assert_eq!(saved, object);
+}
+
+#[test]
+fn fractional_polyline() {
+    let data = r##"
+        {
+          "height":0,
+          "id":18,
+          "name":"",
+          "polyline":[
+          {
+            "x":0,
+            "y":0
+          },
+          {
+            "x":0.5,
+            "y":100.125
+          }],
+          "rotation":0,
+          "type":"",
+          "visible":true,
+          "width":0,
+          "x":0,
+          "y":0
+        }"##;
+    let object: Object = serde_json::from_str(data).unwrap();
+    assert_eq!(
+        object.object_type,
+        ObjectType::PolyLine(vec![
+            Vec2 { x: 0.0, y: 0.0 },
+            Vec2 { x: 0.5, y: 100.125 }
+        ])
+    );
+    assert!(ObjectType::Ellipse.points().is_none());
+}
+
 #[test]
 fn text() {
     let data = r##"
@@ -180,3 +276,138 @@ fn text() {
         }"##;
     assert!(serde_json::from_str::<Object>(data).is_ok());
 }
+
+#[test]
+fn text_defaults() {
+    let data = r##"
+        {
+          "height":19,
+          "id":15,
+          "name":"",
+          "text":
+          {
+            "text":"Hello World"
+          },
+          "rotation":0,
+          "type":"",
+          "visible":true,
+          "width":248,
+          "x":48,
+          "y":136
+        }"##;
+    let object: Object = serde_json::from_str(data).unwrap();
+    let text = match object.object_type {
+        ObjectType::Text(text) => text,
+        other => panic!("expected text, found {:?}", other),
+    };
+    assert_eq!(text.text, "Hello World");
+    assert_eq!(text.font_family, "sans-serif");
+    assert_eq!(text.pixel_size, 16);
+    assert_eq!(text.color, Color(0, 0, 0, 255));
+    assert!(text.kerning);
+    assert!(!(text.wrap || text.bold || text.italic));
+    assert!(!(text.underline || text.strikeout));
+    assert_eq!(text.horizontal_align, HorizontalAlign::Left);
+    assert_eq!(text.vertical_align, VerticalAlign::Top);
+    // Nothing but the text is written back out
+    assert_eq!(
+        serde_json::to_string(&text).unwrap(),
+        r#"{"text":"Hello World"}"#
+    );
+}
+
+#[test]
+fn text_styles() {
+    let data = r##"
+        {
+          "bold":true,
+          "color":"#ff0000",
+          "fontfamily":"Serif",
+          "halign":"justify",
+          "italic":true,
+          "kerning":false,
+          "pixelsize":24,
+          "strikeout":true,
+          "text":"Welcome\nto town",
+          "underline":true,
+          "valign":"center",
+          "wrap":true
+        }"##;
+    let text: Text = serde_json::from_str(data).unwrap();
+    assert_eq!(text.font_family, "Serif");
+    assert_eq!(text.pixel_size, 24);
+    assert_eq!(text.color, Color(255, 0, 0, 255));
+    assert!(text.wrap && text.bold && text.italic);
+    assert!(text.underline && text.strikeout);
+    assert!(!text.kerning);
+    assert_eq!(text.horizontal_align, HorizontalAlign::Justify);
+    assert_eq!(text.vertical_align, VerticalAlign::Center);
+    let saved: Text =
+        serde_json::from_str(&serde_json::to_string(&text).unwrap()).unwrap();
+    assert_eq!(saved, text);
+
+    let data = r#"{ "halign":"middle", "text":"" }"#;
+    assert!(serde_json::from_str::<Text>(data).is_err());
+}
+
+#[test]
+fn shapes() {
+    let object = |fields: &str| -> Object {
+        let data = format!(
+            r#"{{ "height":10, "id":1, "name":"", "rotation":0,
+                  "width":20, "x":0, "y":0 {} }}"#,
+            fields
+        );
+        serde_json::from_str(&data).unwrap()
+    };
+    assert_eq!(object("").object_type, ObjectType::Rectangle);
+    assert_eq!(
+        object(r#", "ellipse":true"#).object_type,
+        ObjectType::Ellipse
+    );
+    assert_eq!(object(r#", "point":true"#).object_type, ObjectType::Point);
+
+    // Flipped horizontally
+    let tile = object(r#", "gid":2147483651"#);
+    match tile.object_type {
+        ObjectType::Tile(gid) => {
+            assert_eq!(gid.id(), 3);
+            assert!(gid.flip_h() && !gid.flip_v());
+        }
+        other => panic!("expected a tile, found {:?}", other),
+    }
+    assert_eq!(tile.gid, Some(Gid::from(2147483651)));
+    let json = serde_json::to_string(&tile).unwrap();
+    assert_eq!(json.matches("\"gid\"").count(), 1);
+    let saved: Object = serde_json::from_str(&json).unwrap();
+    assert_eq!(saved, tile);
+}
+
+#[test]
+fn visibility_and_class() {
+    let data = r##"
+        {
+          "class":"door",
+          "height":0,
+          "id":1,
+          "name":"",
+          "rotation":0,
+          "visible":false,
+          "width":0,
+          "x":0,
+          "y":0
+        }"##;
+    let object: Object = serde_json::from_str(data).unwrap();
+    assert!(!object.visible);
+    assert_eq!(object.class, "door");
+    assert_eq!(object.custom_type, "");
+    let saved: Object =
+        serde_json::from_str(&serde_json::to_string(&object).unwrap()).unwrap();
+    assert_eq!(saved, object);
+
+    let data = r##"
+        { "height":0, "id":1, "name":"", "rotation":0, "width":0 }"##;
+    let object: Object = serde_json::from_str(data).unwrap();
+    assert!(object.visible);
+    assert_eq!(object.class, "");
+}
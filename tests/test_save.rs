@@ -0,0 +1,296 @@
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use tiled_json_rs::{Color, Error, LayerType, Map, Object, TileSet};
+
+/// Every JSON file in tests/data, with the `type` Tiled gave it
+fn data_files() -> Vec<(PathBuf, String)> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir("tests/data").unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().and_then(|e| e.to_str()) != Some("json") {
+            continue;
+        }
+        let json: Value =
+            serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        let kind = json["type"].as_str().unwrap().to_string();
+        files.push((path, kind));
+    }
+    files.sort();
+    files
+}
+
+/// Whether the map's tile layers can be written with this build's codecs
+fn codecs_enabled(path: &Path) -> bool {
+    let name = path.file_name().and_then(|n| n.to_str()).unwrap();
+    (name != "b64zlib.json" || cfg!(feature = "zlib"))
+        && (name != "b64gzip.json" || cfg!(feature = "gzip"))
+        && (name != "b64zstd.json" || cfg!(feature = "zstd"))
+}
+
+fn read_json(path: &Path) -> Value {
+    serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap()
+}
+
+/// Loads `json` as though it were written over the map at `path`, so that
+/// what the map references is found as before
+fn reload(path: &Path, json: &str, resolved: bool) -> Map {
+    let loader = |file: &Path| {
+        if file == path {
+            Ok(json.as_bytes().to_vec())
+        } else {
+            std::fs::read(file)
+        }
+    };
+    if resolved {
+        Map::load_with(path, &loader).unwrap()
+    } else {
+        Map::load_unresolved_with(path, &loader).unwrap()
+    }
+}
+
+#[test]
+fn round_trip_all_data_files() {
+    let files = data_files();
+    assert_eq!(files.len(), 10);
+
+    for (path, kind) in files {
+        match kind.as_str() {
+            "map" if codecs_enabled(&path) => {
+                let map = Map::load_from_file(&path).unwrap();
+                let json = map.to_string().unwrap();
+                let reloaded = reload(&path, &json, true);
+                assert_eq!(map, reloaded, "{}", path.display());
+
+                let map = Map::load_from_file_unresolved(&path).unwrap();
+                let json = map.to_string().unwrap();
+                let reloaded = reload(&path, &json, false);
+                assert_eq!(map, reloaded, "{}", path.display());
+            }
+            "map" => {}
+            "tileset" => {
+                let tile_set: TileSet =
+                    serde_json::from_value(read_json(&path)).unwrap();
+                let json = serde_json::to_string(&tile_set).unwrap();
+                let reloaded: TileSet = serde_json::from_str(&json).unwrap();
+                assert_eq!(tile_set, reloaded, "{}", path.display());
+            }
+            "template" => {
+                let object: Object =
+                    serde_json::from_value(read_json(&path)["object"].clone())
+                        .unwrap();
+                let json = serde_json::to_string(&object).unwrap();
+                let reloaded: Object = serde_json::from_str(&json).unwrap();
+                assert_eq!(object, reloaded, "{}", path.display());
+            }
+            other => panic!("{}: unexpected type {}", path.display(), other),
+        }
+    }
+}
+
+#[test]
+fn written_json_matches_tiled() {
+    let path = Path::new("tests/data/base64.json");
+    let original = read_json(path);
+    let map = Map::load_from_file_unresolved(path).unwrap();
+    let written: Value =
+        serde_json::from_str(&map.to_string().unwrap()).unwrap();
+
+    assert_eq!(written["type"], "map");
+    assert_eq!(written["backgroundcolor"], original["backgroundcolor"]);
+    assert_eq!(written["properties"], original["properties"]);
+    assert_eq!(
+        written["tilesets"][0]["tiles"][0]["properties"],
+        original["tilesets"][0]["tiles"][0]["properties"]
+    );
+
+    let layer = &written["layers"][0]["layers"][1];
+    let original_layer = &original["layers"][0]["layers"][1];
+    assert_eq!(layer["encoding"], "base64");
+    assert_eq!(layer["data"], original_layer["data"]);
+
+    let text = &written["layers"][1]["objects"][4]["text"];
+    assert_eq!(text, &original["layers"][1]["objects"][4]["text"]);
+
+    let path = Path::new("tests/data/csv.json");
+    let original = read_json(path);
+    let mut map = Map::load_from_file_unresolved(path).unwrap();
+    let written: Value =
+        serde_json::from_str(&map.to_string().unwrap()).unwrap();
+    let layer = &written["layers"][0]["layers"][1];
+    assert!(layer.get("encoding").is_none());
+    assert_eq!(layer["data"], original["layers"][0]["layers"][1]["data"]);
+
+    // A map made in code may not have a format version to write
+    map.version.clear();
+    let written: Value =
+        serde_json::from_str(&map.to_string().unwrap()).unwrap();
+    assert!(written.get("version").is_none());
+}
+
+#[test]
+fn template_instances_keep_their_template() {
+    let path = Path::new("tests/data/external_objects.json");
+    let original = read_json(path);
+    let mut map = Map::load_from_file(path).unwrap();
+    let written: Value =
+        serde_json::from_str(&map.to_string().unwrap()).unwrap();
+
+    // Only what the instances set themselves is written with the reference,
+    // and the GID of a tile
+    let objects = &written["layers"][1]["objects"];
+    let original_objects = &original["layers"][1]["objects"];
+    let fields = |object: &Value| -> Vec<String> {
+        let fields = object.as_object().unwrap().keys();
+        fields.filter(|&key| key != "gid").cloned().collect()
+    };
+    for i in 5..10 {
+        assert_eq!(fields(&objects[i]), fields(&original_objects[i]));
+        assert_eq!(objects[i]["template"], original_objects[i]["template"]);
+    }
+    assert!(objects[5]["gid"].is_u64());
+
+    // An instance's changes are written, and the rest left to the template
+    if let LayerType::ObjectGroup(group) = &mut map.layers[1].layer_type {
+        group.objects[5].name = String::from("renamed");
+    }
+    let written: Value =
+        serde_json::from_str(&map.to_string().unwrap()).unwrap();
+    let object = &written["layers"][1]["objects"][5];
+    assert_eq!(object["name"], "renamed");
+    assert_eq!(object["template"], original_objects[5]["template"]);
+    assert!(object.get("rotation").is_none());
+}
+
+#[test]
+fn resolved_tile_sets_are_written_as_references() {
+    let path = Path::new("tests/data/external_tileset.json");
+    let original = read_json(path);
+    let mut map = Map::load_from_file(path).unwrap();
+    let written: Value =
+        serde_json::from_str(&map.to_string().unwrap()).unwrap();
+    assert_eq!(written["tilesets"], original["tilesets"]);
+
+    // Without its source the tileset is embedded
+    if let TileSet::Internal(tile_set) = &mut map.tile_sets[0] {
+        tile_set.source = None;
+    }
+    let written: Value =
+        serde_json::from_str(&map.to_string().unwrap()).unwrap();
+    assert_eq!(written["tilesets"][0]["name"], "base");
+    assert!(written["tilesets"][0].get("source").is_none());
+}
+
+#[test]
+fn compression_is_kept() {
+    let mut files = Vec::new();
+    if cfg!(feature = "zlib") {
+        files.push(("tests/data/b64zlib.json", "zlib"));
+    }
+    if cfg!(feature = "gzip") {
+        files.push(("tests/data/b64gzip.json", "gzip"));
+    }
+    if cfg!(feature = "zstd") {
+        files.push(("tests/data/b64zstd.json", "zstd"));
+    }
+
+    for (file, compression) in files {
+        let map = Map::load_from_file(Path::new(file)).unwrap();
+        let written: Value =
+            serde_json::from_str(&map.to_string().unwrap()).unwrap();
+        let layer = &written["layers"][0]["layers"][1];
+        assert_eq!(layer["encoding"], "base64", "{}", file);
+        assert_eq!(layer["compression"], compression, "{}", file);
+        assert!(layer["data"].is_string(), "{}", file);
+    }
+}
+
+#[test]
+fn compression_level_is_used() {
+    let mut files = Vec::new();
+    if cfg!(feature = "zlib") {
+        files.push("tests/data/b64zlib.json");
+    }
+    if cfg!(feature = "gzip") {
+        files.push("tests/data/b64gzip.json");
+    }
+    if cfg!(feature = "zstd") {
+        files.push("tests/data/b64zstd.json");
+    }
+
+    for file in files {
+        let path = Path::new(file);
+        let mut map = Map::load_from_file(path).unwrap();
+        let data_len = |map: &Map| {
+            let json = map.to_string().unwrap();
+            let reloaded = reload(path, &json, true);
+            assert_eq!(map.layers, reloaded.layers, "{}", file);
+            let written: Value = serde_json::from_str(&json).unwrap();
+            let layer = &written["layers"][0]["layers"][1];
+            layer["data"].as_str().unwrap().len()
+        };
+        let default = data_len(&map);
+        map.compression_level = 1;
+        let fast = data_len(&map);
+        map.compression_level = 9;
+        let small = data_len(&map);
+        assert!(small < fast, "{}", file);
+        assert!(small <= default, "{}", file);
+    }
+
+    if cfg!(feature = "zlib") {
+        let path = Path::new("tests/data/b64zlib.json");
+        let mut map = Map::load_from_file(path).unwrap();
+        map.compression_level = 10;
+        let err = map.to_string().unwrap_err();
+        assert!(matches!(err, Error::Serialize { .. }), "{:?}", err);
+        assert_eq!(err.json_path(), Some("layers[0].layers[1].data"));
+    }
+}
+
+#[test]
+fn save_to_file_can_be_loaded() {
+    let map = Map::load_from_file(Path::new("tests/data/csv.json")).unwrap();
+    let path = std::env::temp_dir()
+        .join(format!("tiled-json-rs-save-{}.json", std::process::id()));
+    map.save_to_file(&path).unwrap();
+    let reloaded = Map::load_from_file(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(map, reloaded.unwrap());
+}
+
+#[test]
+fn colours_are_written_as_tiled_reads_them() {
+    let mut map =
+        Map::load_from_file(Path::new("tests/data/csv.json")).unwrap();
+    map.background_color = Color(16, 32, 48, 255);
+    let written: Value =
+        serde_json::from_str(&map.to_string().unwrap()).unwrap();
+    assert_eq!(written["backgroundcolor"], "#102030");
+    map.background_color = Color(1, 2, 3, 10);
+    let written: Value =
+        serde_json::from_str(&map.to_string().unwrap()).unwrap();
+    assert_eq!(written["backgroundcolor"], "#0a010203");
+}
+
+#[test]
+fn unwritable_values_are_located() {
+    let mut map =
+        Map::load_from_file(Path::new("tests/data/csv.json")).unwrap();
+    map.background_color = Color(256, 0, 0, 255);
+    let err = map.to_string().unwrap_err();
+    assert!(matches!(err, Error::Serialize { .. }), "{:?}", err);
+    assert_eq!(err.json_path(), Some("backgroundcolor"));
+
+    let mut map =
+        Map::load_from_file(Path::new("tests/data/csv.json")).unwrap();
+    if let LayerType::Group { layers } = &mut map.layers[0].layer_type {
+        if let LayerType::ImageLayer(image) = &mut layers[0].layer_type {
+            image.transparent_color = Color(0, 0, 0, 1000);
+        }
+    }
+    let err = map.to_string().unwrap_err();
+    assert_eq!(
+        err.json_path(),
+        Some("layers[0].layers[0].transparentcolor")
+    );
+}
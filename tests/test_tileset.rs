@@ -1,4 +1,5 @@
-use tiled_json_rs::{Color, TileSet};
+use std::path::Path;
+use tiled_json_rs::{Color, Internal, Map, TileRect, TileSet};
 
 #[test]
 fn tileset() {
@@ -138,17 +139,167 @@ fn tileset_wang() {
     assert!(serde_json::from_str::<TileSet>(data).is_ok());
 
     assert_eq!(
-        m.wang_sets.as_ref().unwrap()[0].corner_colors[0].color.r(),
-        61
+        m.internal().wang_sets.as_ref().unwrap()[0].corner_colors[0]
+            .color
+            .r(),
+        211
     );
 
     assert_eq!(
-        m.wang_sets.as_ref().unwrap()[0].corner_colors[0].color,
-        Color(61, 49, 49, 255)
+        m.internal().wang_sets.as_ref().unwrap()[0].corner_colors[0].color,
+        Color(211, 19, 19, 255)
     );
 
     assert_eq!(
-        m.wang_sets.unwrap()[0].wang_tiles[0].wang_id,
+        m.internal().wang_sets.as_ref().unwrap()[0].wang_tiles[0].wang_id,
         [2, 0, 1, 0, 1, 0, 2, 0]
     );
 }
+
+#[test]
+fn source_rects_skip_margin_and_spacing() {
+    let data = r##"
+        {
+         "columns":4,
+         "image":"tall.png",
+         "imageheight":107,
+         "imagewidth":71,
+         "margin":2,
+         "name":"tall",
+         "spacing":1,
+         "tilecount":12,
+         "tileheight":34,
+         "tilewidth":16
+        }"##;
+    let tile_set: Internal = serde_json::from_str(data).unwrap();
+    assert_eq!(
+        tile_set.tile_position_on_image(0),
+        Some(TileRect {
+            x: 2,
+            y: 2,
+            width: 16,
+            height: 34
+        })
+    );
+    // Second column, third row
+    assert_eq!(
+        tile_set.tile_position_on_image(9),
+        Some(TileRect {
+            x: 19,
+            y: 72,
+            width: 16,
+            height: 34
+        })
+    );
+    assert_eq!(tile_set.tile_position_on_image(12), None);
+    assert_eq!(tile_set.tile_image_path(11), Some(Path::new("tall.png")));
+}
+
+#[test]
+fn image_collection_source_rects() {
+    let data = r##"
+        {
+         "columns":0,
+         "firstgid":10,
+         "grid":{ "height":1, "orientation":"orthogonal", "width":1 },
+         "margin":0,
+         "name":"props",
+         "spacing":0,
+         "tilecount":2,
+         "tileheight":64,
+         "tiles":[
+              { "id":0, "image":"barrel.png", "imageheight":48,
+                "imagewidth":32 },
+              { "height":40, "id":3, "image":"sheet.png",
+                "imageheight":256, "imagewidth":256, "width":24, "x":64,
+                "y":128 }],
+         "tilewidth":32
+        }"##;
+    let tile_set: Internal = serde_json::from_str(data).unwrap();
+    assert!(tile_set.is_image_collection());
+    assert_eq!(tile_set.tile_image_path(0), Some(Path::new("barrel.png")));
+    assert_eq!(
+        tile_set.tile_position_on_image(0),
+        Some(TileRect {
+            x: 0,
+            y: 0,
+            width: 32,
+            height: 48
+        })
+    );
+    // A Tiled 1.9 sub-rectangle of a larger image
+    assert_eq!(tile_set.tile_image_path(3), Some(Path::new("sheet.png")));
+    assert_eq!(
+        tile_set.tile_position_on_image(3),
+        Some(TileRect {
+            x: 64,
+            y: 128,
+            width: 24,
+            height: 40
+        })
+    );
+    // IDs can have gaps
+    assert!(tile_set.has_tile(13));
+    assert!(!tile_set.has_tile(11));
+    assert_eq!(tile_set.tile_position_on_image(1), None);
+
+    // Written back without the tileset image Tiled doesn't have
+    let json = serde_json::to_value(&tile_set).unwrap();
+    assert!(json.get("image").is_none());
+    assert_eq!(json["tiles"][1]["x"], 64);
+}
+
+#[test]
+fn unknown_gids_have_no_source_rect() {
+    let map = Map::load_from_file(Path::new("tests/data/csv.json")).unwrap();
+    let tile_set = map.tile_sets[0].internal();
+    let last = tile_set.first_gid + tile_set.tile_count - 1;
+    assert!(map.tile_position_on_image(last).is_some());
+    assert_eq!(map.tile_position_on_image(last + 1), None);
+    assert_eq!(map.tile_image_path(last + 1), None);
+}
+
+#[test]
+fn tile_info() {
+    let map =
+        Map::load_from_file(Path::new("tests/data/external_objects.json"))
+            .unwrap();
+
+    // The second tile, flipped horizontally, with collision shapes
+    let info = map.tile_info(0x8000_0002).unwrap();
+    assert_eq!(info.tile_set.name, "base");
+    assert_eq!(info.local_id, 1);
+    assert_eq!(info.image, Some(Path::new("numbers.png")));
+    // Spaced a pixel apart
+    assert_eq!(
+        info.rect,
+        Some(TileRect {
+            x: 33,
+            y: 0,
+            width: 32,
+            height: 32
+        })
+    );
+    assert!(info.flip_h && !info.flip_v && !info.flip_d);
+    assert!(!info.rotate_hex120);
+    assert!(map.tile_info(0x1000_0002).unwrap().rotate_hex120);
+    assert_eq!(
+        info.properties.map(|p| &p["number"]),
+        Some(&tiled_json_rs::TiledValue::Int(2))
+    );
+    assert!(info.object_group.is_some());
+    assert_eq!(info.tile_type, None);
+
+    let five = map.tile_info(5).unwrap();
+    assert_eq!(five.tile_type, Some("five"));
+    assert_eq!(five.properties.map(|p| p.len()), Some(0));
+    assert_eq!(map.tile_info(7).unwrap().animation.map(<[_]>::len), Some(7));
+
+    // A tile without extra details
+    let plain = map.tile_info(4).unwrap();
+    assert!(plain.tile.is_none() && plain.properties.is_none());
+    assert_eq!(plain.rect.map(|r| (r.x, r.y)), Some((0, 33)));
+
+    assert_eq!(map.tile_info(0), None);
+    assert_eq!(map.tile_info(10), None);
+}
@@ -0,0 +1,207 @@
+#![cfg(feature = "xml")]
+
+use std::path::{Path, PathBuf};
+use tiled_json_rs::{
+    Error, External, InMemory, LayerType, Map, Object, ObjectType, TileSet,
+};
+
+fn load(file: &str) -> Map {
+    Map::load_from_file(Path::new(file)).unwrap()
+}
+
+/// Points template instances at the JSON copies of their TX templates
+fn use_json_templates(objects: &mut [Object]) {
+    for template in objects.iter_mut().flat_map(|o| &mut o.template) {
+        let path = template.path.strip_suffix(".tx").unwrap();
+        template.path = format!("{}.json", path);
+    }
+}
+
+#[test]
+fn tmx_matches_json() {
+    let mut files = vec!["csv", "external_tileset", "external_objects"];
+    if cfg!(feature = "zlib") {
+        files.push("b64zlib");
+    }
+    for file in files {
+        let mut tmx = load(&format!("tests/data/{}.tmx", file));
+        let json = load(&format!("tests/data/{}.json", file));
+        // Some were exported by a different release of Tiled
+        tmx.tiled_version.clone_from(&json.tiled_version);
+        // The TMX references the TSX copy of the tileset
+        for (tmx, json) in tmx.tile_sets.iter_mut().zip(&json.tile_sets) {
+            if let TileSet::Internal(tmx) = tmx {
+                tmx.source.clone_from(&json.internal().source);
+            }
+        }
+        if let LayerType::ObjectGroup(group) = &mut tmx.layers[1].layer_type {
+            use_json_templates(&mut group.objects);
+        }
+        assert_eq!(tmx, json, "{}", file);
+    }
+}
+
+#[test]
+fn tsx_matches_json() {
+    let load = |source: &str| {
+        External {
+            first_gid: 1,
+            source: PathBuf::from(source),
+        }
+        .load(Path::new("tests/data"))
+        .unwrap()
+    };
+    let mut tsx = load("tileset.tsx");
+    assert_eq!(tsx.source, Some(PathBuf::from("tileset.tsx")));
+    tsx.source = Some(PathBuf::from("tileset.json"));
+    assert_eq!(tsx, load("tileset.json"));
+}
+
+#[test]
+fn tx_templates_match_json() {
+    let objects = |file: &str| -> Vec<Object> {
+        match &load(file).layers[1].layer_type {
+            LayerType::ObjectGroup(group) => group.objects.clone(),
+            _ => panic!("Second layer should be an object group"),
+        }
+    };
+    let mut tx = objects("tests/data/external_objects.tmx");
+    use_json_templates(&mut tx);
+    let json = objects("tests/data/external_objects.json");
+    // The objects from index 5 are template instances
+    assert_eq!(tx.len(), 10);
+    for (tx, json) in tx.iter().zip(&json).skip(5) {
+        assert!(!matches!(tx.object_type, ObjectType::Template(_)));
+        assert_eq!(tx, json);
+    }
+}
+
+#[test]
+fn load_from_str_accepts_tmx() {
+    let tmx = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <map version="1.10" orientation="isometric" width="2" height="2"
+             tilewidth="64" tileheight="32" infinite="1" class="level"
+             parallaxoriginx="4">
+         <editorsettings>
+          <chunksize width="32" height="8"/>
+          <export target="level.json" format="json"/>
+         </editorsettings>
+         <properties>
+          <property name="count" type="int" value="3"/>
+         </properties>
+         <layer id="1" name="ground" width="2" height="2" visible="0">
+          <data>
+           <tile gid="1"/>
+           <tile/>
+           <tile gid="2147483650"/>
+           <tile gid="3"/>
+          </data>
+         </layer>
+         <layer id="2" name="chunks" width="2" height="2" opacity="0.5">
+          <data encoding="csv">
+           <chunk x="-16" y="0" width="2" height="1">
+            1,2
+           </chunk>
+          </data>
+         </layer>
+        </map>"#;
+    let map = Map::load_from_str(tmx).unwrap();
+    assert!(map.infinite);
+    assert_eq!(map.version, "1.10");
+    assert_eq!(map.class, "level");
+    assert_eq!(map.parallax_origin_x, 4.0);
+    let settings = map.editor_settings.as_ref().unwrap();
+    assert_eq!(settings.chunk_size.unwrap().width, 32);
+    let export = settings.export.as_ref().unwrap();
+    assert_eq!(export.target.as_deref(), Some("level.json"));
+    assert_eq!(map.properties.len(), 1);
+    assert!(!map.layers[0].visible);
+    assert_eq!(map.layers[1].opacity, 0.5);
+    match &map.layers[0].layer_type {
+        LayerType::TileLayer(tiles) => {
+            assert_eq!(tiles.data, vec![1, 0, 2147483650, 3]);
+            assert!(tiles.data[2].flip_h());
+        }
+        _ => panic!("expected a tile layer"),
+    }
+    match &map.layers[1].layer_type {
+        LayerType::TileLayer(tiles) => {
+            let chunks = tiles.chunks.as_ref().unwrap();
+            assert_eq!(chunks[0].x, -16);
+            assert_eq!(chunks[0].data, vec![1, 2]);
+        }
+        _ => panic!("expected a tile layer"),
+    }
+}
+
+#[test]
+fn xml_errors_are_located() {
+    let err = Map::load_from_str("<map>\n <layer></map>").unwrap_err();
+    match err {
+        Error::Syntax { line, .. } => assert_eq!(line, 2),
+        other => panic!("expected a syntax error, got {:?}", other),
+    }
+
+    let tmx = r#"<map orientation="orthogonal" width="2" height="1"
+                  tilewidth="32" tileheight="32">
+         <layer name="ground" width="2" height="1">
+          <data encoding="csv">1,x</data>
+         </layer>
+        </map>"#;
+    let err = Map::load_from_str(tmx).unwrap_err();
+    assert!(matches!(err, Error::Schema { .. }), "{:?}", err);
+    assert_eq!(err.json_path(), Some("layers[0].data[1]"));
+
+    // Too deep to parse without overflowing the stack. The map is the
+    // first level, so the 128th group is too deep.
+    let depth = 50_000;
+    let tmx = format!(
+        "<map>\n{}{}</map>",
+        "<group>".repeat(depth),
+        "</group>".repeat(depth)
+    );
+    match Map::load_from_str(&tmx).unwrap_err() {
+        Error::Syntax { line, column, .. } => {
+            assert_eq!((line, column), (2, 127 * "<group>".len() + 1))
+        }
+        other => panic!("expected a syntax error, got {:?}", other),
+    }
+    // Comments, self-closing tags and quoted `>`s don't nest
+    let tmx = format!(
+        "<map>{}</map>",
+        "<!-- <group> --><group name='>'/>".repeat(depth)
+    );
+    let err = Map::load_from_str(&tmx).unwrap_err();
+    assert!(matches!(err, Error::Schema { .. }), "{:?}", err);
+}
+
+#[test]
+fn tsx_wang_colors() {
+    let tsx = r##"<?xml version="1.0" encoding="UTF-8"?>
+        <tileset version="1.10" name="terrain" tilewidth="16" tileheight="16"
+                 tilecount="4" columns="2">
+         <image source="terrain.png" width="32" height="32"/>
+         <wangsets>
+          <wangset name="ground" type="corner" tile="-1">
+           <wangcolor name="grass" color="#00ff00" tile="-1" probability="1"/>
+           <wangcolor name="sand" color="#ffff00" tile="3" probability="0.5"/>
+           <wangtile tileid="0" wangid="0,1,0,2,0,1,0,2"/>
+          </wangset>
+         </wangsets>
+        </tileset>"##;
+    let tile_set = External {
+        first_gid: 1,
+        source: PathBuf::from("terrain.tsx"),
+    }
+    .load_with(Path::new(""), &InMemory::new().with("terrain.tsx", tsx))
+    .unwrap();
+    let wang_set = &tile_set.wang_sets.as_ref().unwrap()[0];
+    assert_eq!(wang_set.tile, -1);
+    assert!(wang_set.corner_colors.is_empty());
+    assert!(wang_set.edge_colors.is_empty());
+    assert_eq!(wang_set.colors.len(), 2);
+    assert_eq!(wang_set.colors[0].tile, -1);
+    assert_eq!(wang_set.colors[1].name, "sand");
+    assert_eq!(wang_set.colors[1].probability, 0.5);
+    assert_eq!(wang_set.wang_tiles[0].wang_id, [0, 1, 0, 2, 0, 1, 0, 2]);
+}